@@ -1,5 +1,8 @@
 use core::fmt;
-use std::path::{Path, PathBuf};
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
 use clap::{Parser, Subcommand, ValueEnum};
 use reflexo_typst::error_once;
@@ -39,6 +42,8 @@ pub enum Subcommands {
     Build(BuildArgs),
     #[clap(about = "serve book.")]
     Serve(ServeArgs),
+    #[clap(about = "remove the outputs of the last build.")]
+    Clean(CleanArgs),
 }
 
 /// Determine the approach to retrieving metadata of a book project.
@@ -93,8 +98,15 @@ pub struct CompileArgs {
     pub dir: String,
 
     /// Determine the approach to retrieving metadata of the book project.
-    #[clap(long, default_value = "strict")]
-    pub meta_source: MetaSource,
+    /// (Defaults to `outline` when a main file is given, either as the
+    /// directory argument or by `--entry`, and `strict` otherwise)
+    #[clap(long)]
+    pub meta_source: Option<MetaSource>,
+
+    /// The main file of the book when the meta source is `outline`, relative
+    /// to the book's directory.
+    #[clap(long, value_name = "FILE")]
+    pub entry: Option<PathBuf>,
 
     /// The mode to render typst document. The dynamically rendering means that
     /// some elements will be rendered by a wasm module in the browser.
@@ -162,6 +174,32 @@ pub struct CompileArgs {
     /// Specify a filter to only load files with a specific extension.
     #[clap(long, default_value = "^(player.bilibili.com)$")]
     pub allowed_url_source: Option<String>,
+
+    /// Keep building the rest of the book when some chapters fail to compile.
+    /// An error page is written for each failed chapter, and the build exits
+    /// successfully.
+    #[clap(long)]
+    pub keep_going: bool,
+
+    /// Include the draft chapters. They are always included by `serve`.
+    #[clap(long)]
+    pub drafts: bool,
+
+    /// The deepest heading level that starts a new chapter when the meta
+    /// source is `outline`. The deeper headings stay in the chapter of their
+    /// parent. (Defaults to splitting at every heading level)
+    #[clap(long, value_name = "LEVEL")]
+    pub outline_split_level: Option<NonZeroUsize>,
+
+    /// Turn the top-level headings into part titles when the meta source is
+    /// `outline`, and their sub-headings into chapters.
+    #[clap(long)]
+    pub outline_parts: bool,
+
+    /// Remove the outputs of the last build that are not produced by this
+    /// build, e.g. the pages of renamed or removed chapters.
+    #[clap(long)]
+    pub clean: bool,
 }
 
 impl CompileArgs {
@@ -190,13 +228,46 @@ impl CompileArgs {
 
         let dir = Path::new(&self.dir);
         if dir.is_file() {
-            if self.meta_source == MetaSource::Strict {
+            if self.meta_source == Some(MetaSource::Strict) {
                 return Err(error_once!("project dir is a file", dir: dir.display()));
             }
+            if let Some(entry) = &self.entry {
+                return Err(error_once!(
+                    "cannot specify an entry file when the project dir is a file",
+                    dir: dir.display(),
+                    entry: entry.display(),
+                ));
+            }
+            self.entry = Some(dir.to_owned());
             let w = dir.parent().unwrap().to_str().unwrap().to_owned();
             self.dir = w;
+        } else if let Some(entry) = &self.entry {
+            let entry = Path::new(&self.dir).join(entry);
+            if !entry.is_file() {
+                return Err(error_once!("entry file does not exist", entry: entry.display()));
+            }
+            self.entry = Some(entry);
         }
 
+        let meta_source = match (self.meta_source, &self.entry) {
+            (Some(MetaSource::Strict), Some(entry)) => {
+                return Err(error_once!(
+                    "an entry file is only used when the meta source is outline",
+                    entry: entry.display(),
+                ));
+            }
+            (Some(MetaSource::Outline), None) => {
+                return Err(error_once!(
+                    "the outline meta source requires an entry file, e.g. `--entry main.typ`",
+                    dir: self.dir,
+                ));
+            }
+            (Some(meta_source), _) => meta_source,
+            (None, Some(_)) => MetaSource::Outline,
+            (None, None) => MetaSource::Strict,
+        };
+        self.meta_source = Some(meta_source);
+
         if self.workspace.is_empty() {
             self.workspace.clone_from(&self.dir);
         }
@@ -211,6 +282,10 @@ pub struct InitArgs {
     /// arguments for compile setting.
     #[clap(flatten)]
     pub compile: CompileArgs,
+
+    /// arguments for the dev server started after initializing the book.
+    #[clap(flatten)]
+    pub server: ServerArgs,
 }
 
 #[derive(Default, Debug, Clone, Parser)]
@@ -221,6 +296,14 @@ pub struct BuildArgs {
     pub compile: CompileArgs,
 }
 
+#[derive(Default, Debug, Clone, Parser)]
+#[clap(next_help_heading = "Clean options")]
+pub struct CleanArgs {
+    /// arguments for compile setting, which determine the dest directory.
+    #[clap(flatten)]
+    pub compile: CompileArgs,
+}
+
 #[derive(Default, Debug, Clone, Parser)]
 #[clap(next_help_heading = "Compile options")]
 pub struct ServeArgs {
@@ -232,7 +315,47 @@ pub struct ServeArgs {
     #[clap(long)]
     pub no_build: bool,
 
-    /// Listen address.
+    /// Serve another book under a path to root, e.g. `--book /api/=../api`,
+    /// to test the links across the books. It is built with the same compile
+    /// options and can be repeated.
+    #[clap(
+        long = "book",
+        value_name = "PREFIX=DIR",
+        action = clap::ArgAction::Append,
+        value_parser = parse_input_pair,
+    )]
+    pub books: Vec<(String, String)>,
+
+    /// arguments for the dev server.
+    #[clap(flatten)]
+    pub server: ServerArgs,
+}
+
+#[derive(Default, Debug, Clone, Parser)]
+#[clap(next_help_heading = "Server options")]
+pub struct ServerArgs {
+    /// Listen address. If the port is in use, the following ports are tried.
     #[clap(long, default_value = "127.0.0.1:25520")]
     pub addr: String,
+
+    /// Open the book in the default web browser after building it.
+    #[clap(long)]
+    pub open: bool,
+
+    /// Serve over HTTPS. Without `--tls-cert` and `--tls-key`, a self-signed
+    /// certificate for localhost is generated.
+    #[clap(long)]
+    pub https: bool,
+
+    /// The PEM file of the TLS certificate chain, which implies `--https`.
+    #[clap(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The PEM file of the TLS private key.
+    #[clap(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Add a header to every response, e.g. `'Cross-Origin-Opener-Policy: same-origin'`.
+    #[clap(long = "header", value_name = "HEADER")]
+    pub headers: Vec<String>,
 }
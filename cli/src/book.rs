@@ -7,4 +7,8 @@ use typst::ecow::EcoString;
 pub struct ChapterItem {
     pub title: EcoString,
     pub path: Option<ImmutStr>,
+    /// The section number of the chapter, e.g. `1.2`.
+    pub section: Option<String>,
+    /// Whether the chapter or any of its parents is a draft.
+    pub draft: bool,
 }
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 /// Typst content kind embedded in metadata nodes
@@ -23,6 +25,10 @@ pub enum BookMetaElem {
         #[serde(default)]
         sub: Vec<BookMetaElem>,
         section: Option<String>,
+        /// Whether the chapter is a draft, which is excluded from `build`
+        /// unless `--drafts` is passed.
+        #[serde(default)]
+        draft: bool,
     },
     #[serde(rename = "separator")]
     Separator {},
@@ -70,6 +76,125 @@ pub struct BuildMeta {
     /// option.
     #[serde(rename = "dest-dir")]
     pub dest_dir: String,
+    /// The url of the deployed site, e.g. `https://example.com`, which is used
+    /// to generate the canonical and OpenGraph urls of each page.
+    #[serde(rename = "site-url", default)]
+    pub site_url: String,
+    /// Whether to render the first page of each chapter as its social preview
+    /// image.
+    #[serde(rename = "social-image", default)]
+    pub social_image: bool,
+    /// The template of the not-found page, relative to the book's root
+    /// directory. Defaults to `404.typ` if it exists.
+    #[serde(rename = "not-found", default)]
+    pub not_found: String,
+    /// The policy of the html elements embedded by `shiroa.media`, e.g.
+    /// `iframe`. Defaults to the builtin policy.
+    #[serde(default)]
+    pub embed: Option<EmbedMeta>,
+    /// The redirects of the moved pages, mapping the old paths to the new
+    /// paths, e.g. `guide/old.typ` to `guide/new.typ`.
+    #[serde(default)]
+    pub redirects: BTreeMap<String, String>,
+    /// Whether to compute the content security policy of each page, which is
+    /// emitted as a `<meta http-equiv>` and in the `_headers` file.
+    #[serde(default)]
+    pub csp: bool,
+    /// The handlers of the embedded commands declared by the book or its
+    /// theme, keyed by the command kind.
+    #[serde(default)]
+    pub xcommands: BTreeMap<String, XCommandMeta>,
+}
+
+/// The policy of embedded html elements in build-meta.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct EmbedMeta {
+    /// The allowed url schemes of the `src` attributes. Defaults to `http` and
+    /// `https`.
+    pub schemes: Option<Vec<String>>,
+    /// The allowed tags and their policies. Defaults to `iframe`, `div`,
+    /// `audio` and `video`.
+    pub tags: Option<BTreeMap<String, EmbedTagMeta>>,
+}
+
+/// The policy of an allowed tag of embedded html elements.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct EmbedTagMeta {
+    /// The allowed attributes, besides the `data-*` attributes.
+    pub attributes: Vec<String>,
+    /// The allowed hosts of the `src` attribute, where `*.example.com` matches
+    /// the subdomains. Defaults to the hosts matching `--allowed-url-source`.
+    pub hosts: Option<Vec<String>>,
+    /// The attributes that are always set, e.g. `sandbox`, `loading` and
+    /// `referrerpolicy`, overriding the attributes set by the author.
+    pub force: BTreeMap<String, String>,
+}
+
+/// A handler of embedded commands declared in build-meta, which renders the
+/// payload of a command as an element hydrated by the theme.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct XCommandMeta {
+    /// The tag of the rendered element.
+    pub tag: String,
+    /// The class of the rendered element, which is extended by the `class` in
+    /// the payload.
+    pub class: Option<String>,
+    /// The attributes that are always set on the rendered element.
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Default for XCommandMeta {
+    fn default() -> Self {
+        Self {
+            tag: "div".to_owned(),
+            class: None,
+            attributes: BTreeMap::new(),
+        }
+    }
+}
+
+/// Page metadata in a chapter, set by `#page-meta`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PageMeta {
+    /// Overrides the title of the chapter in the sidebar and the search
+    /// index.
+    pub title: Option<String>,
+    /// Overrides the description of the page.
+    pub description: Option<String>,
+    /// The tags of the page.
+    pub tags: Vec<String>,
+    /// Whether the page is a draft.
+    pub draft: bool,
+    /// The weight of the page among its siblings in the sidebar.
+    pub weight: Option<i64>,
+    /// The canonical URL of the page.
+    pub canonical_url: Option<String>,
+    /// The url of the social preview image of the page.
+    pub image: Option<String>,
+    /// Custom `<meta>` tags of the page, mapping names to contents.
+    pub meta: BTreeMap<String, String>,
+}
+
+/// The title and weight of a chapter set by `page-meta`, which override the
+/// ones in the summary.
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct PageSummary {
+    pub title: Option<String>,
+    pub weight: Option<i64>,
+}
+
+impl PageSummary {
+    /// Gets the summary set by a page, if any.
+    pub fn of_page(meta: &PageMeta) -> Option<Self> {
+        (meta.title.is_some() || meta.weight.is_some()).then(|| Self {
+            title: meta.title.clone(),
+            weight: meta.weight,
+        })
+    }
 }
 
 /// Configuration for how to fold chapters of sidebar.
@@ -3,10 +3,14 @@ use std::num::NonZeroUsize;
 use reflexo_typst::{debug_loc::DocumentPosition, TypstDocument};
 use serde::{Deserialize, Serialize};
 use typst::{
-    foundations::{Content, NativeElement, Packed, StyleChain},
-    introspection::Introspector,
+    comemo::Track,
+    engine::Sink,
+    foundations::{Content, Context, IntoValue, NativeElement, Packed, Scope, StyleChain, Value},
+    introspection::{Introspector, Location},
     model::HeadingElem,
-    syntax::Span,
+    routines::SpanMode,
+    syntax::{Span, SyntaxMode},
+    World,
 };
 
 use crate::utils::interner::SpanInternerImpl;
@@ -19,11 +23,50 @@ pub(crate) struct HeadingNode {
     position: DocumentPosition,
     level: NonZeroUsize,
     bookmarked: bool,
+    label: Option<String>,
+    numbering: Option<String>,
     children: Vec<HeadingNode>,
 }
 
+/// Displays the numbering of a heading by the heading counter of the document
+/// at its location, i.e. `counter(heading).display(numbering)`, which also
+/// respects the counter updates and the numbering functions.
+fn display_numbering(
+    world: &dyn World,
+    introspector: &dyn Introspector,
+    heading: &Packed<HeadingElem>,
+) -> Option<String> {
+    let numbering = heading.numbering.get_ref(StyleChain::default()).as_ref()?;
+    let context = Context::new(Some(heading.location()?), None);
+
+    let mut scope = Scope::new();
+    scope.define("numbering", numbering.clone().into_value());
+    let displayed = typst_eval::eval_string(
+        world.track(),
+        world.library(),
+        Sink::new().track_mut(),
+        introspector.track(),
+        context.track(),
+        "counter(heading).display(numbering)",
+        SpanMode::Uniform(Span::detached()),
+        SyntaxMode::Code,
+        scope,
+    )
+    .ok()?;
+
+    let displayed = match displayed {
+        Value::Str(text) => text.to_string(),
+        value => value.display().plain_text().to_string(),
+    };
+    // The trailing dot is added by themes, e.g. `1.` in sidebars.
+    Some(displayed.trim_end_matches(['.', ' ']).to_owned())
+}
+
 /// Construct the outline for the document.
-pub(crate) fn get_outline(introspector: &(impl Introspector + ?Sized)) -> Option<Vec<HeadingNode>> {
+pub(crate) fn get_outline(
+    world: &dyn World,
+    introspector: &dyn Introspector,
+) -> Option<Vec<HeadingNode>> {
     let mut tree: Vec<HeadingNode> = vec![];
     // Stores the level of the topmost skipped ancestor of the next bookmarked
     // heading. A skipped heading is a heading with 'bookmarked: false', that
@@ -34,7 +77,9 @@ pub(crate) fn get_outline(introspector: &(impl Introspector + ?Sized)) -> Option
     let elements = introspector.query(&HeadingElem::ELEM.select());
     for elem in elements.iter() {
         let heading = elem.to_packed::<HeadingElem>().unwrap();
-        let Some(leaf) = HeadingNode::leaf(introspector, heading) else {
+        let label = elem.label().map(|label| label.resolve().to_string());
+        let numbering = display_numbering(world, introspector, heading);
+        let Some(leaf) = HeadingNode::leaf(introspector, heading, label, numbering) else {
             continue;
         };
 
@@ -103,6 +148,8 @@ impl HeadingNode {
     fn leaf(
         introspector: &(impl Introspector + ?Sized),
         element: &Packed<HeadingElem>,
+        label: Option<String>,
+        numbering: Option<String>,
     ) -> Option<Self> {
         let position = {
             let loc = element.location()?;
@@ -124,6 +171,8 @@ impl HeadingNode {
                 .unwrap_or_else(|| element.outlined.get(StyleChain::default())),
             body: element.body.clone(),
             span: element.span(),
+            label,
+            numbering,
             children: Vec::new(),
         })
     }
@@ -142,12 +191,22 @@ pub struct OutlineItem {
     pub span: Option<String>,
     /// The resolved position in the document.
     pub position: Option<DocumentPosition>,
+    /// The label of the heading, if any.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The displayed numbering of the heading, e.g. `1.2`, if it is numbered.
+    #[serde(default)]
+    pub numbering: Option<String>,
     /// The children of the outline item.
     pub children: Vec<OutlineItem>,
 }
 
-pub fn outline(interner: &mut SpanInternerImpl, document: &TypstDocument) -> Outline {
-    let outline = get_outline(document.introspector());
+pub fn outline(
+    interner: &mut SpanInternerImpl,
+    world: &dyn World,
+    document: &TypstDocument,
+) -> Outline {
+    let outline = get_outline(world, document.introspector());
     let mut items = Vec::with_capacity(outline.as_ref().map_or(0, Vec::len));
 
     for heading in outline.iter().flatten() {
@@ -157,6 +216,49 @@ pub fn outline(interner: &mut SpanInternerImpl, document: &TypstDocument) -> Out
     Outline { items }
 }
 
+/// A heading of a html document, which starts a chapter when the document is
+/// split by its outline.
+pub struct HtmlHeading {
+    pub location: Location,
+    pub level: usize,
+    pub item: OutlineItem,
+}
+
+/// Collects the headings of the document up to the split level, or all the
+/// headings if there is no split level. Unlike [`outline`], the headings are
+/// not resolved to positions, so it also works for html documents.
+pub fn html_headings(
+    world: &dyn World,
+    document: &TypstDocument,
+    split_level: Option<usize>,
+) -> Vec<HtmlHeading> {
+    let introspector = document.introspector();
+    let elements = introspector.query(&HeadingElem::ELEM.select());
+    elements
+        .iter()
+        .filter_map(|elem| {
+            let heading = elem.to_packed::<HeadingElem>()?;
+            let level = heading.resolve_level(StyleChain::default()).get();
+            if split_level.is_some_and(|split| level > split) {
+                return None;
+            }
+
+            Some(HtmlHeading {
+                location: elem.location()?,
+                level,
+                item: OutlineItem {
+                    title: heading.body.plain_text().trim().to_owned(),
+                    span: None,
+                    position: None,
+                    label: elem.label().map(|label| label.resolve().to_string()),
+                    numbering: display_numbering(world, introspector, heading),
+                    children: vec![],
+                },
+            })
+        })
+        .collect()
+}
+
 fn outline_item(interner: &mut SpanInternerImpl, src: &HeadingNode, res: &mut Vec<OutlineItem>) {
     let body = src.body.clone();
     let title = body.plain_text().trim().to_owned();
@@ -180,6 +282,8 @@ fn outline_item(interner: &mut SpanInternerImpl, src: &HeadingNode, res: &mut Ve
         title,
         span: Some(span.to_hex()),
         position: Some(src.position),
+        label: src.label.clone(),
+        numbering: src.numbering.clone(),
         children,
     });
 }
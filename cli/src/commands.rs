@@ -1,5 +1,6 @@
 mod init;
 mod serve;
+mod tls;
 
 pub use init::init;
 pub use serve::serve;
@@ -1,20 +1,36 @@
-use std::{convert::Infallible, net::SocketAddr};
+use std::{
+    collections::BTreeSet,
+    convert::Infallible,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 use reflexo_typst::{error::prelude::*, ImmutStr};
 use serde::{Deserialize, Serialize};
-use tokio::io::AsyncReadExt;
-use warp::{Filter, Reply};
+use tokio::net::TcpListener;
+use warp::{
+    filters::BoxedFilter,
+    http::{HeaderMap, HeaderName, HeaderValue},
+    Filter, Reply,
+};
 
+use super::tls;
 use crate::{
-    args::ServeArgs,
-    project::{Project, ServeEvent, WatchSignal},
-    tui_hint,
+    args::{CompileArgs, ServeArgs},
+    project::{read_redirects, Project, ServeEvent, WatchSignal, WatchStatus, NOT_FOUND_PAGE},
+    render::{is_fingerprinted_asset, ContentSecurityPolicy},
+    tui_hint, tui_info, tui_warn,
+    utils::open_browser,
 };
 
+/// The number of the following ports tried when the port is in use.
+const PORT_RETRIES: u16 = 16;
+
 const LIVE_RELOAD_SERVER_EVENT: &str = r#"
 <script>
   console.log("Live reload script loaded");
-  const u = new URL("/live-reload", window.location.origin);
+  const u = new URL("{url_base}live-reload", window.location.origin);
   u.searchParams.set("location", window.location.pathname);
   const eventSource = new EventSource(u);
 
@@ -25,7 +41,7 @@ const LIVE_RELOAD_SERVER_EVENT: &str = r#"
   };
 
   const heartbeat = () => {
-    const u = new URL("/heartbeat", window.location.origin);
+    const u = new URL("{url_base}heartbeat", window.location.origin);
     u.searchParams.set("location", window.location.pathname);
     fetch(u).catch((err) => console.error("Failed to send heartbeat:", err));
   };
@@ -34,30 +50,62 @@ const LIVE_RELOAD_SERVER_EVENT: &str = r#"
 </script>
 "#;
 
-// todo: clean code here, but I'm tired.
-pub async fn serve(args: ServeArgs) -> Result<()> {
-    // #[cfg(feature = "tokio-console")]
-    // console_subscriber::init();
+/// A book served by the dev server, with its own routes under the path to
+/// root, live reload channel and watch loop.
+struct ServedBook {
+    proj: Project,
+    url_base: String,
+    hb_tx: tokio::sync::mpsc::UnboundedSender<ServeEvent>,
+    hb_rx: tokio::sync::mpsc::UnboundedReceiver<ServeEvent>,
+    backend_tx: tokio::sync::broadcast::Sender<WatchSignal>,
+    status: Arc<Mutex<WatchStatus>>,
+}
 
-    let mut proj = Project::new(args.compile)?;
+impl ServedBook {
+    fn new(proj: Project) -> Self {
+        let url_base = proj.tr.ctx.url_base.to_string();
+        let (hb_tx, hb_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (backend_tx, _) = tokio::sync::broadcast::channel(128);
+        // The redirects of the existing build are served until the book is
+        // built, e.g. with `--no-build`.
+        let status = WatchStatus {
+            redirects: read_redirects(&proj.dest_dir),
+            ..Default::default()
+        };
+        Self {
+            proj,
+            url_base,
+            hb_tx,
+            hb_rx,
+            backend_tx,
+            status: Arc::new(Mutex::new(status)),
+        }
+    }
 
-    let http_addr: SocketAddr = args
-        .addr
-        .clone()
-        .parse()
-        .map_err(map_string_err("ParseServeAddr"))?;
-    let dest_dir = proj.dest_dir.clone();
+    /// Creates the routes of the book, which are mounted under the path to
+    /// root, e.g. `/shiroa/`, as the book is deployed.
+    fn routes(&self) -> BoxedFilter<(warp::reply::Response,)> {
+        let url_base = self.url_base.clone();
+        let dest_dir = self.proj.dest_dir.clone();
+        let mount = url_base
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .fold(warp::any().boxed(), |mount: BoxedFilter<()>, segment| {
+                mount.and(warp::path(segment.to_owned())).boxed()
+            });
+        let script: Arc<str> = LIVE_RELOAD_SERVER_EVENT
+            .replace("{url_base}", &url_base)
+            .into();
 
-    let (hb_tx, hb_rx) = tokio::sync::mpsc::unbounded_channel();
-    let (backend_tx, _) = tokio::sync::broadcast::channel(128);
-    let btx = backend_tx.clone();
+        let hb_tx = self.hb_tx.clone();
+        let btx = self.backend_tx.clone();
 
-    #[derive(Serialize, Deserialize)]
-    struct LocationQuery {
-        location: String,
-    }
+        #[derive(Serialize, Deserialize)]
+        struct LocationQuery {
+            location: String,
+        }
 
-    let live_reload = warp::path("live-reload").and(warp::get()).map(move || {
+        let live_reload = mount.clone().and(warp::path("live-reload")).and(warp::get()).map(move || {
             let mut backend_rx = btx.subscribe();
             warp::sse::reply(warp::sse::keep_alive().stream(async_stream::stream! {
                 while let Ok(WatchSignal::Reload) = backend_rx.recv().await {
@@ -66,61 +114,443 @@ pub async fn serve(args: ServeArgs) -> Result<()> {
                 }
             }))
         });
-    let heartbeat = warp::path("heartbeat")
-        .and(warp::get())
-        .and(warp::query::<LocationQuery>())
-        .map(move |query: LocationQuery| {
-            let location = ImmutStr::from(query.location);
-            let _ = hb_tx.send(ServeEvent::HoldPath(location.clone(), true));
-            let hb_tx = hb_tx.clone();
-            tokio::spawn(async move {
-                // Simulate some work
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-                let _ = hb_tx.send(ServeEvent::HoldPath(location, false));
+        let base = url_base.trim_end_matches('/').to_owned();
+        let heartbeat = mount
+            .clone()
+            .and(warp::path("heartbeat"))
+            .and(warp::get())
+            .and(warp::query::<LocationQuery>())
+            .map(move |query: LocationQuery| {
+                // The locations are relative to the path to root.
+                let location = query
+                    .location
+                    .strip_prefix(&base)
+                    .unwrap_or(&query.location);
+                let location = ImmutStr::from(location);
+                let _ = hb_tx.send(ServeEvent::HoldPath(location.clone(), true));
+                let hb_tx = hb_tx.clone();
+                tokio::spawn(async move {
+                    // Simulate some work
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    let _ = hb_tx.send(ServeEvent::HoldPath(location, false));
+                });
+                warp::reply::with_status("", warp::http::StatusCode::OK)
             });
-            warp::reply::with_status("", warp::http::StatusCode::OK)
-        });
-    let fallback = warp::fs::dir(dest_dir).map(|reply: warp::filters::fs::File| {
-        if reply.path().extension().is_some_and(|ext| ext == "html") {
-            let file = match std::fs::File::open(reply.path()) {
-                Ok(file) => file,
-                Err(e) => {
-                    tui_hint!("Failed to open file: {e}");
-                    return warp::reply::with_status(
-                        "File not found",
-                        warp::http::StatusCode::NOT_FOUND,
-                    )
-                    .into_response();
+        // The local endpoints to control the watch loop. The pages of other
+        // sites can't trigger a rebuild.
+        let rebuild_tx = self.hb_tx.clone();
+        let rebuild = mount
+            .clone()
+            .and(warp::path!("__shiroa" / "rebuild"))
+            .and(warp::post())
+            .and(warp::header::optional::<String>("origin"))
+            .and(warp::header::optional::<String>("host"))
+            .map(move |origin: Option<String>, host: Option<String>| {
+                if !is_same_origin(origin.as_deref(), host.as_deref()) {
+                    return warp::reply::with_status("", warp::http::StatusCode::FORBIDDEN);
                 }
-            };
-            let body = tokio::fs::File::from_std(file);
+                let _ = rebuild_tx.send(ServeEvent::Rebuild);
+                warp::reply::with_status("", warp::http::StatusCode::ACCEPTED)
+            });
+        let watch_status = self.status.clone();
+        let status = mount
+            .clone()
+            .and(warp::path!("__shiroa" / "status"))
+            .and(warp::get())
+            .map(move || warp::reply::json(&*watch_status.lock().unwrap()));
+        // Redirects the moved pages by the `_redirects` written by the build,
+        // before the stub pages at the old paths are served.
+        let redirects = self.status.clone();
+        let redirect = mount
+            .clone()
+            .and(warp::get())
+            .and(warp::path::full())
+            .and_then(move |path: warp::path::FullPath| {
+                let redirects = &redirects.lock().unwrap().redirects;
+                // The old pages are also requested without the extension.
+                let target = redirects
+                    .get(path.as_str())
+                    .or_else(|| redirects.get(&format!("{}.html", path.as_str())))
+                    .cloned();
+                async move {
+                    let target = target.and_then(|target| target.parse::<warp::http::Uri>().ok());
+                    match target {
+                        Some(target) => Ok(warp::redirect::redirect(target)),
+                        None => Err(warp::reject::not_found()),
+                    }
+                }
+            });
+
+        let html_script = script.clone();
+        let fallback = mount.clone().and(warp::fs::dir(dest_dir.clone())).map(
+            move |reply: warp::filters::fs::File| {
+                if reply.path().extension().is_some_and(|ext| ext == "html") {
+                    html_response(reply.path(), &html_script)
+                } else {
+                    // The fingerprinted assets change their names with the content.
+                    let immutable = is_fingerprinted_asset(reply.path());
+                    let mut resp = reply.into_response();
+                    if immutable {
+                        resp.headers_mut().insert(
+                            "Cache-Control",
+                            "public, max-age=31536000, immutable".parse().unwrap(),
+                        );
+                    }
+                    resp
+                }
+            },
+        );
+
+        // Serves the not-found page for the unknown urls under the book, as
+        // the static hosts do.
+        let not_found_page = dest_dir.join(NOT_FOUND_PAGE);
+        let not_found = mount.and(warp::get()).and_then(move || {
+            let page = not_found_page.clone();
+            let script = script.clone();
+            async move {
+                if !page.is_file() {
+                    return Err(warp::reject::not_found());
+                }
+                let mut resp = html_response(&page, &script);
+                *resp.status_mut() = warp::http::StatusCode::NOT_FOUND;
+                Ok(resp)
+            }
+        });
 
-            let stream =
-                tokio_util::io::ReaderStream::new(body.chain(LIVE_RELOAD_SERVER_EVENT.as_bytes()));
+        live_reload
+            .map(Reply::into_response)
+            .boxed()
+            .or(heartbeat
+                .boxed()
+                .or(rebuild.boxed())
+                .or(status.boxed())
+                .or(redirect.boxed())
+                .or(fallback.boxed())
+                .or(not_found.boxed())
+                .with(warp::compression::gzip())
+                .map(Reply::into_response))
+            .unify()
+            .boxed()
+    }
+}
 
-            let mut resp = warp::reply::Response::new(warp::hyper::body::Body::wrap_stream(stream));
-            resp.headers_mut()
-                .insert("Content-Type", "text/html".parse().unwrap());
-            resp
-        } else {
-            reply.into_response()
+// todo: clean code here, but I'm tired.
+pub async fn serve(mut args: ServeArgs) -> Result<()> {
+    // #[cfg(feature = "tokio-console")]
+    // console_subscriber::init();
+
+    // Draft chapters are always previewed.
+    args.compile.drafts = true;
+    let mut books = vec![ServedBook::new(Project::new(args.compile.clone())?)];
+    // The other books are built with the same options, but under their own
+    // paths to root.
+    for (prefix, dir) in &args.books {
+        let compile = CompileArgs {
+            dir: dir.clone(),
+            path_to_root: prefix.clone(),
+            dest_dir: String::new(),
+            entry: None,
+            meta_source: None,
+            ..args.compile.clone()
+        };
+        books.push(ServedBook::new(Project::new(compile)?));
+    }
+
+    let mut url_bases = BTreeSet::new();
+    for book in &books {
+        if !url_bases.insert(book.url_base.clone()) {
+            bail!("cannot serve multiple books at {}", book.url_base);
+        }
+    }
+
+    let http_addr: SocketAddr = args
+        .server
+        .addr
+        .parse()
+        .map_err(map_string_err("ParseServeAddr"))?;
+    let url_base = books[0].url_base.clone();
+
+    // The books under the longer paths are matched first, since a book at `/`
+    // matches every url.
+    let mut order = (0..books.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| std::cmp::Reverse(books[idx].url_base.len()));
+    let routes = order
+        .iter()
+        .map(|&idx| books[idx].routes())
+        .reduce(|routes, book| routes.or(book).unify().boxed())
+        .unwrap();
+
+    // Redirects the site root to the main book if it is mounted elsewhere.
+    let root_uri: warp::http::Uri = url_base.parse().map_err(map_string_err("ParseUrlBase"))?;
+    let root = warp::path::end().and(warp::get()).and_then(move || {
+        let root_uri = root_uri.clone();
+        async move {
+            if root_uri.path() == "/" {
+                return Err(warp::reject::not_found());
+            }
+            Ok(warp::redirect::found(root_uri))
         }
     });
 
-    let server = live_reload.boxed().or(heartbeat
-        .boxed()
-        .or(fallback.boxed())
-        .with(warp::compression::gzip()));
+    let headers = parse_headers(&args.server.headers)?;
+    let server = routes
+        .or(root.boxed())
+        .with(warp::reply::with::headers(headers));
 
-    let (addr, server) = warp::serve(server).bind_ephemeral(http_addr);
-    tui_hint!("Server started at http://{addr}");
+    let https = args.server.https || args.server.tls_cert.is_some();
+    let acceptor = if https {
+        Some(tls::acceptor(&args.server)?)
+    } else {
+        None
+    };
+
+    let listener = bind_with_retries(http_addr).await?;
+    let addr = listener
+        .local_addr()
+        .map_err(map_string_err("ServeLocalAddr"))?;
+    let scheme = if https { "https" } else { "http" };
+    let url = format!("{scheme}://{addr}{url_base}");
+    tui_hint!("Server started at {url}");
+    for book in &books[1..] {
+        tui_hint!(
+            "Serving {} at {scheme}://{addr}{}",
+            book.proj.args.dir,
+            book.url_base
+        );
+    }
 
-    // Build the book if it hasn't been built yet
+    let backend_txs = books
+        .iter()
+        .map(|book| book.backend_tx.clone())
+        .collect::<Vec<_>>();
+    let statuses = books
+        .iter()
+        .map(|book| book.status.clone())
+        .collect::<Vec<_>>();
+
+    // Build the books if they haven't been built yet
     if !args.no_build {
-        tokio::spawn(async move { proj.watch(hb_rx, backend_tx, Some(addr)).await });
+        // Only the main book is opened in the browser.
+        let mut open = args.server.open;
+        for book in books {
+            let ServedBook {
+                mut proj,
+                url_base,
+                hb_rx,
+                backend_tx,
+                status,
+                ..
+            } = book;
+            let url = format!("{scheme}://{addr}{url_base}");
+            let open_book = std::mem::take(&mut open);
+            tokio::spawn(async move {
+                proj.watch(hb_rx, backend_tx, status, Some(url), open_book)
+                    .await
+            });
+        }
+    } else if args.server.open {
+        open_browser(&url);
+    }
+
+    // Stops the server on Ctrl-C, after the open connections are closed.
+    let shutdown = async move {
+        let _ = tokio::signal::ctrl_c().await;
+        tui_info!(h "Stopping", "the server");
+        // The live reload streams never end by themselves.
+        for tx in backend_txs {
+            let _ = tx.send(WatchSignal::Shutdown);
+        }
     };
+    match acceptor {
+        Some(acceptor) => {
+            let incoming = tls::incoming(listener, acceptor);
+            warp::serve(server)
+                .serve_incoming_with_graceful_shutdown(incoming, shutdown)
+                .await;
+        }
+        None => {
+            let incoming = async_stream::stream! {
+                loop {
+                    yield listener.accept().await.map(|(stream, _)| stream);
+                }
+            };
+            warp::serve(server)
+                .serve_incoming_with_graceful_shutdown(incoming, shutdown)
+                .await;
+        }
+    }
 
-    server.await;
+    // Don't leave the pages half written.
+    while statuses
+        .iter()
+        .any(|status| status.lock().unwrap().building)
+    {
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+    }
 
     Ok(())
 }
+
+/// Parses the headers added to every response, e.g. `Name: value`.
+fn parse_headers(headers: &[String]) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for header in headers {
+        let Some((name, value)) = header.split_once(':') else {
+            bail!("invalid header {header}: expected `Name: value`");
+        };
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(map_string_err("ParseHeaderName"))?;
+        let value =
+            HeaderValue::from_str(value.trim()).map_err(map_string_err("ParseHeaderValue"))?;
+        map.append(name, value);
+    }
+
+    Ok(map)
+}
+
+/// Whether a request is sent from the pages of the server, i.e. its `Origin`
+/// is at the `Host` of the request. The requests without `Origin` are not
+/// sent by the pages of other sites.
+fn is_same_origin(origin: Option<&str>, host: Option<&str>) -> bool {
+    let Some(origin) = origin else {
+        return true;
+    };
+    let Some(origin) = url::Url::parse(origin).ok() else {
+        return false;
+    };
+    let Some(origin_host) = origin.host_str() else {
+        return false;
+    };
+
+    let authority = match origin.port() {
+        Some(port) => format!("{origin_host}:{port}"),
+        None => origin_host.to_owned(),
+    };
+    host.is_some_and(|host| host.eq_ignore_ascii_case(&authority))
+}
+
+/// Responds with a html page, with the live reload script appended.
+fn html_response(path: &Path, script: &str) -> warp::reply::Response {
+    let html = match std::fs::read_to_string(path) {
+        Ok(html) => html,
+        Err(e) => {
+            tui_hint!("Failed to open file: {e}");
+            return warp::reply::with_status("File not found", warp::http::StatusCode::NOT_FOUND)
+                .into_response();
+        }
+    };
+
+    // The policy is sent as a header instead, which also allows the live
+    // reload script.
+    let (html, csp) = match ContentSecurityPolicy::take_meta(&html) {
+        Some((html, mut csp)) => {
+            csp.allow_inline_scripts(script);
+            (html, Some(csp))
+        }
+        None => (html, None),
+    };
+
+    let body = format!("{html}{script}");
+    let mut resp = warp::reply::Response::new(body.into());
+    resp.headers_mut()
+        .insert("Content-Type", "text/html".parse().unwrap());
+    if let Some(policy) = csp.and_then(|csp| csp.to_string().parse().ok()) {
+        resp.headers_mut().insert("Content-Security-Policy", policy);
+    }
+    resp
+}
+
+/// Binds the address, or the following ports if the port is in use.
+async fn bind_with_retries(mut addr: SocketAddr) -> Result<TcpListener> {
+    for _ in 0..PORT_RETRIES {
+        match TcpListener::bind(addr).await {
+            Ok(listener) => return Ok(listener),
+            Err(err) if err.kind() == std::io::ErrorKind::AddrInUse && addr.port() != 0 => {
+                let Some(next) = addr.port().checked_add(1) else {
+                    break;
+                };
+                tui_warn!("port {} is in use, trying {next}", addr.port());
+                addr.set_port(next);
+            }
+            Err(err) => {
+                return Err(error_once!("cannot bind the server", addr: addr, err: err));
+            }
+        }
+    }
+
+    bail!("cannot bind the server: no free port up to {addr}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::args::RenderMode;
+
+    #[test]
+    fn origins_are_checked_against_hosts() {
+        let host = Some("localhost:25520");
+        assert!(is_same_origin(None, host));
+        assert!(is_same_origin(Some("http://localhost:25520"), host));
+        assert!(is_same_origin(Some("https://LocalHost:25520"), host));
+        assert!(is_same_origin(
+            Some("http://[::1]:25520"),
+            Some("[::1]:25520")
+        ));
+        assert!(is_same_origin(Some("https://book.dev"), Some("book.dev")));
+
+        assert!(!is_same_origin(Some("http://evil.com"), host));
+        assert!(!is_same_origin(Some("http://localhost:8080"), host));
+        assert!(!is_same_origin(Some("null"), host));
+        assert!(!is_same_origin(Some("http://localhost:25520"), None));
+    }
+
+    #[test]
+    fn headers_are_parsed() {
+        let headers = [
+            "X-Frame-Options: DENY".to_owned(),
+            "cache-control:no-store".to_owned(),
+            "X-Test: a: b ".to_owned(),
+            "X-Test: c".to_owned(),
+        ];
+        let map = parse_headers(&headers).unwrap();
+        assert_eq!(map["x-frame-options"], "DENY");
+        assert_eq!(map["cache-control"], "no-store");
+        let tests = map.get_all("x-test").iter().collect::<Vec<_>>();
+        assert_eq!(tests, ["a: b", "c"]);
+
+        assert!(parse_headers(&["X-Frame-Options".to_owned()]).is_err());
+        assert!(parse_headers(&["Bad Name: x".to_owned()]).is_err());
+        assert!(parse_headers(&["X-Test: a\nb".to_owned()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn moved_pages_are_redirected() {
+        let dir = std::env::temp_dir().join(format!("shiroa-serve-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.typ"), "= Intro\n").unwrap();
+        let proj = Project::new(CompileArgs {
+            dir: dir.to_string_lossy().into_owned(),
+            entry: Some("main.typ".into()),
+            mode: RenderMode::StaticHtml,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // The stub page of the old path is written by the build.
+        std::fs::create_dir_all(&proj.dest_dir).unwrap();
+        std::fs::write(proj.dest_dir.join("old-path.html"), "stub").unwrap();
+        let book = ServedBook::new(proj);
+        book.status.lock().unwrap().redirects =
+            BTreeMap::from([("/old-path.html".to_owned(), "/new-path.html".to_owned())]);
+
+        let routes = book.routes();
+        for path in ["/old-path", "/old-path.html"] {
+            let resp = warp::test::request().path(path).reply(&routes).await;
+            assert_eq!(resp.status(), warp::http::StatusCode::MOVED_PERMANENTLY);
+            assert_eq!(resp.headers()["location"], "/new-path.html");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! Serves the book over HTTPS, e.g. for the Web APIs that require a secure
+//! context.
+
+use std::{io::BufReader, path::Path, sync::Arc};
+
+use futures_util::Stream;
+use reflexo_typst::error::prelude::*;
+use tokio::{net::TcpListener, sync::mpsc};
+use tokio_rustls::{
+    rustls::{
+        crypto::ring::default_provider,
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+        ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+use crate::args::ServerArgs;
+
+/// Creates the TLS acceptor by the certificate and key in the arguments, or
+/// by a self-signed certificate for localhost.
+pub(crate) fn acceptor(args: &ServerArgs) -> Result<TlsAcceptor> {
+    let (certs, key) = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => (read_certs(cert)?, read_key(key)?),
+        _ => self_signed()?,
+    };
+
+    let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(map_string_err("tls: unsupported protocol versions"))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(map_string_err("tls: invalid certificate or key"))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accepts the connections and performs the handshakes concurrently, so that
+/// a slow client doesn't block the others.
+pub(crate) fn incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> impl Stream<Item = std::io::Result<TlsStream<tokio::net::TcpStream>>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    log::warn!("tls: failed to accept connection: {err}");
+                    continue;
+                }
+            };
+
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let _ = tx.send(stream);
+                    }
+                    Err(err) => log::warn!("tls: handshake failed: {err}"),
+                }
+            });
+        }
+    });
+
+    async_stream::stream! {
+        while let Some(stream) = rx.recv().await {
+            yield Ok(stream);
+        }
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).map_err(error_once_map!("tls: open certificate"))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error_once_map!("tls: read certificate"))?;
+    if certs.is_empty() {
+        bail!("tls: no certificate in {}", path.display());
+    }
+
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path).map_err(error_once_map!("tls: open private key"))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(error_once_map!("tls: read private key"))?
+        .context("tls: no private key in the file")
+}
+
+fn self_signed() -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let names = vec!["localhost".to_owned(), "127.0.0.1".to_owned()];
+    let certified = rcgen::generate_simple_self_signed(names)
+        .map_err(map_string_err("tls: generate self-signed certificate"))?;
+
+    let cert = certified.cert.der().clone();
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    Ok((vec![cert], key.into()))
+}
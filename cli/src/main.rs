@@ -2,7 +2,7 @@ use std::process::exit;
 
 use clap::{Args, Command, FromArgMatches};
 use shiroa::{
-    args::{BuildArgs, InitArgs, Opts, ServeArgs, Subcommands},
+    args::{BuildArgs, CleanArgs, InitArgs, Opts, ServeArgs, Subcommands},
     commands,
     error::prelude::*,
     project::Project,
@@ -51,6 +51,10 @@ fn main() {
             args.compile.compat();
             async_continue(async { serve(args).await.unwrap_or_exit() })
         }
+        Some(Subcommands::Clean(mut args)) => {
+            args.compile.compat();
+            clean(args).unwrap_or_exit()
+        }
         None => help_sub_command(),
     };
 
@@ -65,7 +69,7 @@ async fn init(args: InitArgs) -> Result<()> {
 
     serve(ServeArgs {
         compile: args.compile,
-        addr: "127.0.0.1:25520".to_owned(),
+        server: args.server,
         ..Default::default()
     })
     .await?;
@@ -74,8 +78,16 @@ async fn init(args: InitArgs) -> Result<()> {
 }
 
 fn build(args: BuildArgs) -> Result<()> {
+    let keep_going = args.compile.keep_going;
     let mut proj = Project::new(args.compile)?;
-    proj.build()?;
+    proj.build()?.finish(keep_going)?;
+
+    exit(0)
+}
+
+fn clean(args: CleanArgs) -> Result<()> {
+    let proj = Project::new(args.compile)?;
+    proj.clean()?;
 
     exit(0)
 }
@@ -1,26 +1,37 @@
+mod clean;
 mod compile;
 mod meta;
+mod redirect;
 mod release;
+mod report;
 mod watch;
 
 use core::fmt;
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use ::typst::ecow::EcoString;
 use serde::{Deserialize, Serialize};
 
-pub(crate) use self::watch::{ServeEvent, WatchSignal};
+pub(crate) use self::redirect::read_redirects;
+pub use self::report::{BuildReport, ChapterReport, ChapterStatus};
+pub(crate) use self::watch::{ServeEvent, WatchSignal, WatchStatus};
 use crate::{
     args::{CompileArgs, MetaSource, RenderMode},
     book::{
-        meta::{BookMeta, BuildMeta},
+        meta::{BookMeta, BuildMeta, PageMeta},
         ChapterItem,
     },
     error::prelude::*,
-    render::{SearchRenderer, TypstRenderer},
-    utils::{create_dirs, write_file},
+    render::{
+        asset_manifest, SearchRenderer, Taxonomy, TypstRenderer, ASSET_MANIFEST, INTERNAL_ASSETS,
+    },
+    utils::create_dirs,
 };
 
+/// The not-found page in the dest directory, which is served for the unknown
+/// urls by the static hosts and `serve`.
+pub const NOT_FOUND_PAGE: &str = "404.html";
+
 /// Typst content kind embedded in metadata nodes
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "func")]
@@ -31,6 +42,54 @@ enum JsonContent {
     Space {},
     #[serde(rename = "text")]
     Text { text: String },
+    #[serde(rename = "linebreak")]
+    Linebreak {},
+    #[serde(rename = "parbreak")]
+    Parbreak {},
+    #[serde(rename = "smartquote")]
+    SmartQuote {
+        #[serde(default)]
+        double: Option<bool>,
+    },
+    #[serde(rename = "raw")]
+    Raw { text: String },
+    #[serde(rename = "styled")]
+    Styled { child: Box<JsonContent> },
+    #[serde(
+        rename = "strong",
+        alias = "emph",
+        alias = "underline",
+        alias = "overline",
+        alias = "strike",
+        alias = "highlight",
+        alias = "smallcaps",
+        alias = "sub",
+        alias = "super",
+        alias = "link",
+        alias = "box",
+        alias = "equation"
+    )]
+    Body {
+        /// The body, which is absent in an empty `box`.
+        #[serde(default)]
+        body: Option<Box<JsonContent>>,
+    },
+    #[serde(rename = "attach")]
+    Attach {
+        base: Box<JsonContent>,
+        #[serde(default)]
+        t: Option<Box<JsonContent>>,
+        #[serde(default)]
+        b: Option<Box<JsonContent>>,
+    },
+    #[serde(rename = "frac")]
+    Frac {
+        num: Box<JsonContent>,
+        denom: Box<JsonContent>,
+    },
+    /// Content that has no textual representation, e.g. images.
+    #[serde(other)]
+    Unknown,
 }
 
 impl fmt::Display for JsonContent {
@@ -41,8 +100,28 @@ impl fmt::Display for JsonContent {
                     ch.fmt(f)?
                 }
             }
-            Self::Space {} => f.write_str(" ")?,
-            Self::Text { text } => f.write_str(text)?,
+            Self::Space {} | Self::Linebreak {} | Self::Parbreak {} => f.write_str(" ")?,
+            Self::Text { text } | Self::Raw { text } => f.write_str(text)?,
+            Self::SmartQuote { double } => {
+                f.write_str(if double.unwrap_or(true) { "\"" } else { "'" })?
+            }
+            Self::Styled { child } => child.fmt(f)?,
+            Self::Body { body } => {
+                if let Some(body) = body {
+                    body.fmt(f)?
+                }
+            }
+            Self::Attach { base, t, b } => {
+                base.fmt(f)?;
+                if let Some(b) = b {
+                    write!(f, "_{b}")?;
+                }
+                if let Some(t) = t {
+                    write!(f, "^{t}")?;
+                }
+            }
+            Self::Frac { num, denom } => write!(f, "{num}/{denom}")?,
+            Self::Unknown => {}
         }
 
         Ok(())
@@ -56,17 +135,25 @@ pub struct Project {
     pub book_meta: BookMeta,
     pub build_meta: Option<BuildMeta>,
     pub chapters: Vec<ChapterItem>,
+    /// The tags of the chapters, which are kept across incremental builds.
+    pub taxonomy: Taxonomy,
 
     pub dest_dir: PathBuf,
     pub args: CompileArgs,
     pub meta_source: MetaSource,
+    /// The entry file of the book in outline mode.
+    pub outline_entry: Option<PathBuf>,
+    /// The statically rendered chapters split from the entry file in outline
+    /// mode, keyed by the chapter path.
+    pub outline_pages: HashMap<String, String>,
 }
 
 impl Project {
     pub fn new(mut args: CompileArgs) -> Result<Self> {
         args.canonicalize()?;
 
-        let meta_source = args.meta_source;
+        // The meta source is always determined after canonicalization.
+        let meta_source = args.meta_source.unwrap_or_default();
         let render_mode = args.mode;
         let tr = TypstRenderer::new(args.clone());
 
@@ -81,6 +168,9 @@ impl Project {
             book_meta: Default::default(),
             build_meta: None,
             chapters: vec![],
+            taxonomy: Taxonomy::default(),
+            outline_entry: None,
+            outline_pages: HashMap::new(),
         };
 
         release::release_builtin_packages(&mut proj.tr.universe_mut().snapshot());
@@ -89,45 +179,29 @@ impl Project {
         Ok(proj)
     }
 
-    pub fn build(&mut self) -> Result<()> {
+    pub fn build(&mut self) -> Result<BuildReport> {
         let sr = SearchRenderer::new();
         self.extract_assets(&sr)?;
-        self.compile_once(&Default::default(), sr)?;
-
-        Ok(())
+        let report = self.compile_once(&Default::default(), sr)?;
+        self.update_outputs(&report)?;
+        Ok(report)
     }
 
     fn extract_assets(&mut self, sr: &SearchRenderer) -> Result<()> {
         // copy internal files
         create_dirs(self.dest_dir.join("internal"))?;
-        write_file(
-            self.dest_dir.join("internal/typst_ts_renderer_bg.wasm"),
-            include_bytes!("../../assets/artifacts/typst_ts_renderer_bg.wasm"),
-        )?;
-        write_file(
-            self.dest_dir.join("internal/svg_utils.js"),
-            include_bytes!("../../assets/artifacts/svg_utils.js"),
-        )?;
-        write_file(
-            self.dest_dir.join("internal/shiroa.js"),
-            include_bytes!("../../assets/artifacts/shiroa.js"),
-        )?;
-
-        if sr.config.copy_js {
-            write_file(
-                self.dest_dir.join("internal/searcher.js"),
-                include_bytes!("../../assets/artifacts/searcher.js"),
-            )?;
-            write_file(
-                self.dest_dir.join("internal/mark.min.js"),
-                include_bytes!("../../assets/artifacts/mark.min.js"),
-            )?;
-            write_file(
-                self.dest_dir.join("internal/elasticlunr.min.js"),
-                include_bytes!("../../assets/artifacts/elasticlunr.min.js"),
-            )?;
+        let manifest = asset_manifest();
+        let written = &self.tr.ctx.written;
+        let assets = INTERNAL_ASSETS.iter();
+        for asset in assets.filter(|asset| sr.config.copy_js || !asset.search) {
+            let path = self.dest_dir.join("internal").join(&manifest[asset.name]);
+            written.write(path, asset.content)?;
         }
 
+        let manifest =
+            serde_json::to_string_pretty(manifest).context("cannot serialize asset manifest")?;
+        written.write(self.dest_dir.join(ASSET_MANIFEST), manifest)?;
+
         Ok(())
     }
 
@@ -161,4 +235,6 @@ impl Project {
 pub struct ChapterArtifact {
     pub description: Option<EcoString>,
     pub content: String,
+    /// The metadata set by `#page-meta` in the chapter.
+    pub meta: PageMeta,
 }
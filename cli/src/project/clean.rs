@@ -0,0 +1,150 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use reflexo_typst::path::unix_slash;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::prelude::*,
+    project::{BuildReport, Project},
+    tui_info, tui_warn,
+    utils::write_file,
+};
+
+/// The manifest of the files produced by the last build, in the dest
+/// directory.
+const OUTPUT_MANIFEST: &str = ".shiroa-outputs.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OutputManifest {
+    /// The produced files, relative to the dest directory.
+    files: BTreeSet<String>,
+}
+
+impl Project {
+    /// Records the files produced by the build in the output manifest. With
+    /// `--clean`, the outputs of the last build that are not produced again
+    /// are removed.
+    pub(super) fn update_outputs(&self, report: &BuildReport) -> Result<()> {
+        let last = self.read_outputs()?;
+        let mut outputs = OutputManifest {
+            files: report
+                .outputs
+                .iter()
+                .filter_map(|path| path.strip_prefix(&self.dest_dir).ok())
+                .map(unix_slash)
+                .filter(|path| path != OUTPUT_MANIFEST)
+                .collect(),
+        };
+
+        // The failed chapters may not write their outputs, which are kept.
+        if report.has_failures() {
+            outputs.files.extend(last.files);
+        } else if self.args.clean {
+            let stale = last.files.difference(&outputs.files);
+            let removed = self.remove_outputs(stale)?;
+            if removed > 0 {
+                tui_info!(h "Cleaned", "{removed} stale output(s)");
+            }
+        }
+
+        let manifest =
+            serde_json::to_string_pretty(&outputs).context("cannot serialize output manifest")?;
+        write_file(self.dest_dir.join(OUTPUT_MANIFEST), manifest)
+    }
+
+    /// Removes all the outputs recorded by the last build, and the manifest.
+    pub fn clean(&self) -> Result<()> {
+        let manifest = self.dest_dir.join(OUTPUT_MANIFEST);
+        if !manifest.is_file() {
+            tui_warn!(
+                "no output manifest in {}, nothing to clean",
+                self.dest_dir.display()
+            );
+            return Ok(());
+        }
+
+        let last = self.read_outputs()?;
+        let removed = self.remove_outputs(&last.files)?;
+        fs::remove_file(&manifest).map_err(error_once_map!("clean: remove manifest"))?;
+        tui_info!(h "Cleaned", "{removed} output(s) in {}", self.dest_dir.display());
+
+        Ok(())
+    }
+
+    fn read_outputs(&self) -> Result<OutputManifest> {
+        let manifest = self.dest_dir.join(OUTPUT_MANIFEST);
+        if !manifest.is_file() {
+            return Ok(OutputManifest::default());
+        }
+
+        let content = fs::read(&manifest).map_err(error_once_map!("read output manifest"))?;
+        serde_json::from_slice(&content).context("cannot parse output manifest")
+    }
+
+    /// Removes the outputs, and the directories that become empty. A file is
+    /// only removed if it is in the dest directory, which doesn't contain the
+    /// book's root directory.
+    fn remove_outputs<'a>(&self, files: impl IntoIterator<Item = &'a String>) -> Result<usize> {
+        let dest_dir = self
+            .dest_dir
+            .canonicalize()
+            .map_err(error_once_map!("clean: resolve dest dir"))?;
+        let root_dir = self
+            .tr
+            .ctx
+            .root_dir
+            .canonicalize()
+            .map_err(error_once_map!("clean: resolve root dir"))?;
+        if root_dir.starts_with(&dest_dir) {
+            return Err(error_once!(
+                "refuse to remove outputs in a dest directory containing the book",
+                dest_dir: dest_dir.display(),
+            ));
+        }
+
+        let mut removed = 0;
+        for file in files {
+            if !dest_dir.join(file).exists() {
+                continue;
+            }
+            let Some(path) = resolve_output(&dest_dir, file) else {
+                tui_warn!("skip removing {file}: not a file in the dest directory");
+                continue;
+            };
+
+            fs::remove_file(&path).map_err(error_once_map!("clean: remove file"))?;
+            removed += 1;
+
+            // Removes the empty parent directories, which fails on the first
+            // non-empty one.
+            let parents = path.ancestors().skip(1);
+            let parents = parents.take_while(|dir| *dir != dest_dir && dir.starts_with(&dest_dir));
+            for dir in parents {
+                if fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Resolves an output recorded in the manifest, which must be an existing
+/// file in the (canonicalized) dest directory after following symlinks.
+fn resolve_output(dest_dir: &Path, file: &str) -> Option<PathBuf> {
+    let file = Path::new(file);
+    if !file
+        .components()
+        .all(|c| matches!(c, Component::Normal(..)))
+    {
+        return None;
+    }
+
+    let path = dest_dir.join(file).canonicalize().ok()?;
+    (path.starts_with(dest_dir) && path.is_file()).then_some(path)
+}
@@ -1,21 +1,38 @@
-use std::{collections::BTreeMap, path::Path, sync::Mutex};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use ::typst::ecow::{eco_format, EcoString};
 use log::warn;
-use reflexo_typst::{static_html, ImmutStr};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use reflexo_typst::{path::unix_slash, static_html, CompilerExt, ImmutStr, TypstDocument};
 
 use crate::{
-    args::MetaSource,
+    args::{MetaSource, RenderMode},
     book::{
-        meta::{BookMetaContent, BookMetaElem},
+        meta::{BookMetaContent, BookMetaElem, PageMeta, PageSummary},
         ChapterItem,
     },
     error::prelude::*,
-    project::{ChapterArtifact, JsonContent, Project},
-    render::{HtmlRenderContext, SearchCtx, SearchRenderer},
-    tui_error, tui_info,
+    project::{
+        BuildReport, ChapterArtifact, ChapterReport, ChapterStatus, JsonContent, Project,
+        NOT_FOUND_PAGE,
+    },
+    render::{
+        CompilePageSetting, ContentSecurityPolicy, HtmlRenderContext, SearchCtx, SearchRenderer,
+        Tag, TaxonomyCtx, TypstRenderTask, HEADERS_FILE, TAGS_INDEX,
+    },
+    tui_error, tui_info, tui_warn,
+    utils::write_file,
 };
 
+/// The template of the social preview images in the book's root directory.
+const OG_CARD_TEMPLATE: &str = "og-card.typ";
+/// The default template of the not-found page in the book's root directory.
+const NOT_FOUND_TEMPLATE: &str = "404.typ";
+
 impl Project {
     pub(super) fn need_compile(&self) -> bool {
         matches!(self.meta_source, MetaSource::Strict)
@@ -25,67 +42,197 @@ impl Project {
         &mut self,
         ac: &BTreeMap<ImmutStr, usize>,
         mut sr: SearchRenderer,
-    ) -> Result<()> {
-        self.prepare_chapters();
+    ) -> Result<BuildReport> {
+        if self.meta_source == MetaSource::Strict {
+            self.chapters = self.generate_chapters(&self.book_meta.summary);
+        }
+
+        let full_build = BTreeMap::new();
+        let mut ac = ac;
+        let mut rendered_again = false;
+        let (mut report, search_items, taxonomy_ctx) = loop {
+            let serach_ctx = SearchCtx {
+                config: &sr.config,
+                items: Mutex::new(vec![]),
+            };
+            let taxonomy_ctx = TaxonomyCtx::default();
+            let page_drafts = Mutex::new(BTreeSet::new());
+            let page_summary = Mutex::new(BTreeMap::new());
 
-        let serach_ctx = SearchCtx {
-            config: &sr.config,
-            items: Mutex::new(vec![]),
+            let report = self.tr.render_chapters(
+                HtmlRenderContext {
+                    search: &serach_ctx,
+                    taxonomy: &taxonomy_ctx,
+                    page_drafts: &page_drafts,
+                    page_summary: &page_summary,
+                    dest_dir: &self.dest_dir,
+                    url_base: &self.tr.ctx.url_base,
+                    keep_going: self.args.keep_going,
+                    include_drafts: self.args.drafts,
+                },
+                &self.chapters, // todo: only render changed
+                ac,
+                |path| self.compile_chapter(path, CompilePageSetting::default()),
+            )?;
+
+            // The drafts, titles and weights set by `page-meta` are only known
+            // after compiling the chapters, but every page applies them to its
+            // summary and navigation, so the book is rendered again once they
+            // change.
+            let page_drafts = page_drafts.into_inner().unwrap();
+            let page_summary = page_summary.into_inner().unwrap();
+            if rendered_again || !self.update_page_meta(ac, page_drafts, page_summary) {
+                let search_items = serach_ctx.items.into_inner().unwrap();
+                break (report, search_items, taxonomy_ctx);
+            }
+            ac = &full_build;
+            rendered_again = true;
         };
 
-        self.tr.render_chapters(
-            HtmlRenderContext {
-                search: &serach_ctx,
-                dest_dir: &self.dest_dir,
-            },
-            &self.chapters, // todo: only render changed
-            ac,
-            |path| self.compile_chapter(path),
-        )?;
+        // Tag pages list all chapters, so they are rendered again whenever the
+        // tags of any chapter change.
+        let tags_changed = if ac.is_empty() {
+            self.taxonomy.replace(taxonomy_ctx)
+        } else {
+            let rendered = report.chapters.iter();
+            let rendered = rendered.filter(|ch| matches!(ch.status, ChapterStatus::Success));
+            self.taxonomy
+                .update(taxonomy_ctx, rendered.map(|ch| ch.path.as_ref()))
+        };
+        if ac.is_empty() || tags_changed {
+            let tags = self.render_tags(self.taxonomy.tags())?;
+            report.chapters.extend(tags);
+        }
+        if ac.is_empty() {
+            report.chapters.extend(self.render_not_found());
+        }
+        if ac.is_empty() && self.tr.ctx.social_image && self.has_og_card() {
+            report.chapters.extend(self.render_og_cards());
+        }
+        if ac.is_empty() {
+            self.write_redirects()?;
+        }
 
-        sr.build(&serach_ctx.items.into_inner().unwrap())?;
+        sr.build(&search_items)?;
 
         if sr.config.copy_js {
-            sr.render_search_index(&self.dest_dir)?;
+            sr.render_search_index(&self.dest_dir, &self.tr.ctx.written)?;
+        }
+
+        report.outputs = self.tr.ctx.written.take();
+        if ac.is_empty() && self.csp_enabled() {
+            let headers = self.write_headers(&report.outputs)?;
+            report.outputs.insert(headers);
         }
 
-        Ok(())
+        Ok(report)
     }
 
-    fn prepare_chapters(&mut self) {
-        match self.meta_source {
-            MetaSource::Strict => self.chapters = self.generate_chapters(&self.book_meta.summary),
-            MetaSource::Outline => {}
+    /// Updates the drafts, titles and weights set by `page-meta` with the ones
+    /// found in the chapters rendered by `ac`, or all chapters if it is empty.
+    /// Returns whether they are changed.
+    fn update_page_meta(
+        &mut self,
+        ac: &BTreeMap<ImmutStr, usize>,
+        found_drafts: BTreeSet<String>,
+        found_summary: BTreeMap<String, PageSummary>,
+    ) -> bool {
+        if self.meta_source != MetaSource::Strict {
+            return false;
+        }
+
+        let mut page_drafts = self.tr.ctx.page_drafts.clone();
+        let mut page_summary = self.tr.ctx.page_summary.clone();
+        if ac.is_empty() {
+            page_drafts.clear();
+            page_summary.clear();
+        } else {
+            page_drafts.retain(|path| !ac.contains_key(path.as_str()));
+            page_summary.retain(|path, _| !ac.contains_key(path.as_str()));
+        }
+        // The drafts are not hidden if they are included in the book.
+        if !self.args.drafts {
+            page_drafts.extend(found_drafts);
+        }
+        page_summary.extend(found_summary);
+        if page_drafts == self.tr.ctx.page_drafts && page_summary == self.tr.ctx.page_summary {
+            return false;
         }
+
+        self.tr.ctx.page_drafts = page_drafts;
+        self.tr.ctx.page_summary = page_summary;
+        self.chapters = self.generate_chapters(&self.book_meta.summary);
+        true
     }
 
     pub(super) fn generate_chapters(&self, meta: &[BookMetaElem]) -> Vec<ChapterItem> {
         let mut chapters = vec![];
+        self.collect_chatpers(meta, false, &mut chapters);
+        chapters
+    }
 
-        for item in meta.iter() {
-            self.collect_chatpers(item, &mut chapters);
+    /// Collects the chapters in `elems` and their sub chapters. The adjacent
+    /// chapters are ordered by the weights set by `page-meta`, followed by the
+    /// ones without weights in the summary order, like the sidebar.
+    fn collect_chatpers(
+        &self,
+        elems: &[BookMetaElem],
+        in_draft: bool,
+        chapters: &mut Vec<ChapterItem>,
+    ) {
+        let is_chapter = |elem: &BookMetaElem| matches!(elem, BookMetaElem::Chapter { .. });
+        for run in elems.chunk_by(|a, b| is_chapter(a) == is_chapter(b)) {
+            let mut run = run
+                .iter()
+                .map(|elem| {
+                    let mut items = vec![];
+                    let weight = self.collect_chapter(elem, in_draft, &mut items);
+                    (weight, items)
+                })
+                .collect::<Vec<_>>();
+            run.sort_by_key(|(weight, _)| (weight.is_none(), *weight));
+            chapters.extend(run.into_iter().flat_map(|(_, items)| items));
         }
-
-        chapters
     }
 
-    fn collect_chatpers(&self, elem: &BookMetaElem, chapters: &mut Vec<ChapterItem>) {
+    /// Collects a chapter and its sub chapters, returning the weight of the
+    /// chapter set by `page-meta`.
+    fn collect_chapter(
+        &self,
+        elem: &BookMetaElem,
+        in_draft: bool,
+        chapters: &mut Vec<ChapterItem>,
+    ) -> Option<i64> {
         match elem {
             BookMetaElem::Separator {} | BookMetaElem::Part { .. } | BookMetaElem::Partbreak {} => {
+                None
             }
             BookMetaElem::Chapter {
-                title, link, sub, ..
+                title,
+                link,
+                sub,
+                section,
+                draft,
             } => {
-                let title = self.evaluate_content(title);
+                let page = link.as_ref().and_then(|p| self.tr.ctx.page_summary.get(p));
+                let title = match page.and_then(|page| page.title.as_deref()) {
+                    Some(title) => title.into(),
+                    None => self.evaluate_content(title),
+                };
+                let page_draft = link.as_ref();
+                let page_draft = page_draft.is_some_and(|p| self.tr.ctx.page_drafts.contains(p));
+                // The sub chapters of a draft are also drafts.
+                let draft = in_draft || *draft || page_draft;
 
                 chapters.push(ChapterItem {
                     title,
                     path: link.as_deref().map(|p| p.into()),
+                    section: section.clone(),
+                    draft,
                 });
 
-                for child in sub.iter() {
-                    self.collect_chatpers(child, chapters);
-                }
+                self.collect_chatpers(sub, draft, chapters);
+                page.and_then(|page| page.weight)
             }
         }
     }
@@ -94,20 +241,162 @@ impl Project {
         match title {
             BookMetaContent::PlainText { content } => content.into(),
             BookMetaContent::Raw { content } => {
-                if let Ok(c) = serde_json::from_value::<JsonContent>(content.clone()) {
-                    return eco_format!("{c}");
+                let title = serde_json::from_value::<JsonContent>(content.clone())
+                    .map(|c| eco_format!("{c}"));
+                match title {
+                    Ok(title) if !title.trim().is_empty() => title.trim().into(),
+                    _ => {
+                        warn!("unevaluated {content:#?}");
+                        "unevaluated title".into()
+                    }
                 }
+            }
+        }
+    }
+
+    /// Renders `tags/index.html` and a `tags/{slug}.html` for each tag by the
+    /// `tags.typ` template in the book's root directory, if any.
+    fn render_tags(&self, tags: Vec<Tag>) -> Result<Vec<ChapterReport>> {
+        const TAGS_TEMPLATE: &str = "tags.typ";
+
+        if tags.is_empty() || !self.tr.ctx.root_dir.join(TAGS_TEMPLATE).is_file() {
+            return Ok(vec![]);
+        }
+
+        let tags_json = serde_json::to_string(&tags).context("cannot serialize tags")?;
+        let index = (format!("tags/{TAGS_INDEX}.typ"), None);
+        let pages = std::iter::once(index)
+            .chain(tags.iter().map(|tag| {
+                let path = format!("tags/{}.typ", tag.slug);
+                (path, Some(tag.name.clone()))
+            }))
+            .collect::<Vec<_>>();
+
+        pages
+            .into_par_iter()
+            .map(|(path, tag)| {
+                let instant = std::time::Instant::now();
+
+                let mut inputs = vec![("x-tags".to_owned(), tags_json.clone())];
+                inputs.extend(tag.map(|tag| ("x-tag".to_owned(), tag)));
+                let settings = CompilePageSetting {
+                    entry: Some(TAGS_TEMPLATE.into()),
+                    inputs,
+                    ..Default::default()
+                };
+
+                let status = match self.compile_chapter(&path, settings) {
+                    Ok(art) => {
+                        let dest = self.dest_dir.join(&path).with_extension("html");
+                        self.tr.ctx.written.write(dest, art.content)?;
+                        ChapterStatus::Success
+                    }
+                    Err(err) => ChapterStatus::Failed(eco_format!("{err}")),
+                };
 
-                warn!("unevaluated {content:#?}");
-                "unevaluated title".into()
+                Ok(ChapterReport {
+                    path: path.into(),
+                    status,
+                    elapsed: instant.elapsed(),
+                })
+            })
+            .collect()
+    }
+
+    /// Renders `404.html` by the template declared by `not-found` in
+    /// build-meta, or the `404.typ` template in the book's root directory, if
+    /// any.
+    fn render_not_found(&self) -> Option<ChapterReport> {
+        let declared = self.build_meta.as_ref().map(|meta| meta.not_found.as_str());
+        let declared = declared.filter(|template| !template.is_empty());
+        let template = declared.unwrap_or(NOT_FOUND_TEMPLATE);
+        if !self.tr.ctx.root_dir.join(template).is_file() {
+            if declared.is_some() {
+                tui_warn!("the not-found template {template} does not exist");
             }
+            return None;
         }
+
+        let instant = std::time::Instant::now();
+        let path = Path::new(NOT_FOUND_PAGE).with_extension("typ");
+        let path = path.to_string_lossy();
+        let settings = CompilePageSetting {
+            entry: Some(template.into()),
+            inputs: vec![("x-not-found".to_owned(), "true".to_owned())],
+            ..Default::default()
+        };
+
+        let written = &self.tr.ctx.written;
+        let status = match self.compile_chapter(&path, settings) {
+            Ok(art) => match written.write(self.dest_dir.join(NOT_FOUND_PAGE), art.content) {
+                Ok(()) => ChapterStatus::Success,
+                Err(err) => ChapterStatus::Failed(eco_format!("{err}")),
+            },
+            Err(err) => ChapterStatus::Failed(eco_format!("{err}")),
+        };
+
+        Some(ChapterReport {
+            path: path.into(),
+            status,
+            elapsed: instant.elapsed(),
+        })
+    }
+
+    /// Whether the `og-card.typ` template exists in the book's root directory.
+    pub(super) fn has_og_card(&self) -> bool {
+        self.tr.ctx.root_dir.join(OG_CARD_TEMPLATE).is_file()
+    }
+
+    /// Renders the social preview image of each chapter by the `og-card.typ`
+    /// template, instead of the first page of the chapter.
+    fn render_og_cards(&self) -> Vec<ChapterReport> {
+        let include_drafts = self.args.drafts;
+        let chapters = self.chapters.iter();
+        let chapters = chapters.filter(|ch| include_drafts || !ch.draft);
+        let chapters = chapters
+            .filter_map(|ch| Some((ch, ch.path.clone()?)))
+            .collect::<Vec<_>>();
+
+        chapters
+            .into_par_iter()
+            .map(|(ch, path)| {
+                let instant = std::time::Instant::now();
+
+                let card = serde_json::json!({
+                    "title": ch.title,
+                    "book-title": self.book_meta.title,
+                    "section": ch.section,
+                });
+                let settings = CompilePageSetting {
+                    entry: Some(OG_CARD_TEMPLATE.into()),
+                    inputs: vec![("x-og-card".to_owned(), card.to_string())],
+                    ..Default::default()
+                };
+
+                let path = Path::new(path.as_ref());
+                let status = match self.tr.render_social_image(path, &settings) {
+                    Ok(()) => ChapterStatus::Success,
+                    Err(err) => {
+                        tui_error!("{}: og card error: {err}", path.display());
+                        ChapterStatus::Failed(eco_format!("{err}"))
+                    }
+                };
+
+                ChapterReport {
+                    path: path.with_extension("png").to_string_lossy().into(),
+                    status,
+                    elapsed: instant.elapsed(),
+                }
+            })
+            .collect()
     }
 
-    fn compile_chapter(&self, path: &str) -> Result<ChapterArtifact> {
+    fn compile_chapter(&self, path: &str, settings: CompilePageSetting) -> Result<ChapterArtifact> {
         tui_info!(h "Compiling", "{path}");
         let instant = std::time::Instant::now();
-        let res = self.compile_chapter_(path);
+        let res = self.compile_chapter_(path, settings);
+        let res = res.map(|artifact| self.expand_xcommands(path, artifact));
+        let res = res.map(|artifact| self.apply_csp(artifact));
         let elapsed = instant.elapsed();
         if let Err(e) = &res {
             tui_error!("{path}: compile error: {e}");
@@ -118,25 +407,356 @@ impl Project {
         res
     }
 
-    fn compile_chapter_(&self, path: &str) -> Result<ChapterArtifact> {
-        // todo: description for single document
-        let task_doc = if self.need_compile() {
-            Some(self.tr.compile_page(Path::new(path))?)
-        } else {
-            None
-        };
+    /// Renders the commands embedded by `xcommand` in the html output of a
+    /// chapter.
+    fn expand_xcommands(&self, path: &str, mut artifact: ChapterArtifact) -> ChapterArtifact {
+        let (content, failures) = self.tr.ctx.xcommands.expand_html(&artifact.content);
+        for failure in &failures {
+            let (line, column) = line_column(&artifact.content, failure.offset);
+            tui_warn!(
+                "{path}: {} at {line}:{column} of the html output, the command is dropped",
+                failure.message
+            );
+        }
 
-        let (task, html_doc) = task_doc.context("no task document")?;
+        artifact.content = content;
+        artifact
+    }
+
+    fn csp_enabled(&self) -> bool {
+        self.build_meta.as_ref().is_some_and(|meta| meta.csp)
+    }
+
+    /// Emits the content security policy of a chapter in its `<head>`.
+    fn apply_csp(&self, mut artifact: ChapterArtifact) -> ChapterArtifact {
+        if !self.csp_enabled() {
+            return artifact;
+        }
+
+        // The renderer module is compiled in the dyn-paged modes.
+        let wasm = self.render_mode != RenderMode::StaticHtml;
+        let policy = &self.tr.ctx.embed_policy;
+        let csp = ContentSecurityPolicy::of_page(&artifact.content, policy, wasm);
+        artifact.content = csp.insert_meta(&artifact.content);
+        artifact
+    }
+
+    /// Writes the content security policy of each page written by the build
+    /// to the `_headers` file for static hosts, returning the path of the file.
+    fn write_headers(&self, outputs: &BTreeSet<PathBuf>) -> Result<PathBuf> {
+        let mut pages = vec![];
+        for path in outputs {
+            let Ok(rel) = path.strip_prefix(&self.dest_dir) else {
+                continue;
+            };
+            if rel.extension().is_none_or(|ext| ext != "html") {
+                continue;
+            }
+
+            let html = std::fs::read_to_string(path).map_err(error_once_map!("read page"))?;
+            let Some((_, csp)) = ContentSecurityPolicy::take_meta(&html) else {
+                continue;
+            };
+
+            let url = format!("{}{}", self.tr.ctx.url_base, unix_slash(rel));
+            if let Some(dir) = url.strip_suffix("index.html") {
+                pages.push((dir.to_owned(), csp.clone()));
+            }
+            pages.push((url, csp));
+        }
+
+        let headers = ContentSecurityPolicy::render_headers(&pages);
+        let path = self.dest_dir.join(HEADERS_FILE);
+        write_file(&path, headers)?;
+        Ok(path)
+    }
+
+    fn compile_chapter_(
+        &self,
+        path: &str,
+        settings: CompilePageSetting,
+    ) -> Result<ChapterArtifact> {
+        let (task, html_doc) = match self.meta_source {
+            MetaSource::Strict => {
+                // Only the chapters get social images, but not the generated
+                // pages compiled from other entries.
+                let chapter = settings.entry.is_none();
+                if chapter && self.tr.ctx.social_image && !self.has_og_card() {
+                    let settings = CompilePageSetting::default();
+                    self.tr.render_social_image(Path::new(path), &settings)?;
+                }
+                self.tr.compile_page_with(Path::new(path), settings)?
+            }
+            // The generated pages, i.e. the tag pages and the not-found page,
+            // are compiled from their own templates rather than split from the
+            // book.
+            MetaSource::Outline if settings.entry.is_some() => {
+                self.tr.compile_page_with(Path::new(path), settings)?
+            }
+            MetaSource::Outline => {
+                // todo: description for single document
+                if let Some(content) = self.outline_pages.get(path) {
+                    return Ok(ChapterArtifact {
+                        content: content.clone(),
+                        description: None,
+                        meta: PageMeta::default(),
+                    });
+                }
+
+                let entry = self.outline_entry.as_deref();
+                let entry = entry.context("no entry file in outline mode")?;
+                self.tr.compile_outline_wrapper(Path::new(path), entry)?
+            }
+        };
 
         let res = task
             .report(static_html(&html_doc))
-            .expect("failed to render static html");
+            .context("failed to render static html")?;
 
         let content = task.report(res.html()).unwrap_or_default().to_owned();
 
+        let doc = TypstDocument::Html(html_doc.clone());
+        let meta = self.query_page_meta(&task, &doc)?;
+
+        let description = meta
+            .description
+            .as_deref()
+            .map(EcoString::from)
+            .or_else(|| res.description().cloned());
+
         Ok(ChapterArtifact {
             content,
-            description: res.description().cloned(),
+            description,
+            meta,
         })
     }
+
+    fn query_page_meta(&self, task: &TypstRenderTask, doc: &TypstDocument) -> Result<PageMeta> {
+        // The last `page-meta` wins, as `get-page-meta` does in the templates.
+        let meta = self.query_meta::<PageMeta>("<shiroa-page-meta>", |item| {
+            let res = task.graph.query(item.to_string(), doc);
+            let mut items = task
+                .report(res)
+                .context("cannot retrieve metadata item(s)")?;
+            Ok(items.pop().into_iter().collect())
+        })?;
+
+        Ok(meta.unwrap_or_default())
+    }
+}
+
+/// Gets the 1-based line and column of a byte offset in a text.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |idx| idx + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{args::CompileArgs, version::SHIROA_PACKAGE_VERSION};
+
+    /// Builds a book from the sources in a temporary directory.
+    fn build_book(name: &str, files: &[(&str, &str)], args: CompileArgs) -> Project {
+        let dir = std::env::temp_dir().join(format!("shiroa-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let mut proj = Project::new(CompileArgs {
+            dir: dir.to_string_lossy().into_owned(),
+            ..args
+        })
+        .unwrap();
+        let report = proj.build().unwrap();
+        for ch in &report.chapters {
+            assert!(
+                matches!(ch.status, ChapterStatus::Success),
+                "{}: {:?}",
+                ch.path,
+                ch.status
+            );
+        }
+
+        proj
+    }
+
+    /// Gets the `book.typ` of a book in strict mode, which sets the metadata
+    /// without the package.
+    fn book_meta(chapters: &[&str], build_meta: &str) -> String {
+        let summary = chapters.iter().map(|link| {
+            format!("(kind: \"chapter\", title: (kind: \"plain-text\", content: \"{link}\"), link: \"{link}\", section: none),")
+        });
+        let summary = summary.collect::<String>();
+        format!(
+            "#metadata((package: (version: \"{SHIROA_PACKAGE_VERSION}\"))) <shiroa-internal-package-meta>\n\
+            #metadata((\n\
+              title: \"Book\", authors: (), description: \"\", repository_edit: \"\", language: \"en\",\n\
+              summary: ({summary}),\n\
+            )) <shiroa-book-meta>\n\
+            #metadata((\"dest-dir\": \"dist\", {build_meta})) <shiroa-build-meta>\n"
+        )
+    }
+
+    /// Reads a page in the dest directory of a built book.
+    fn read_page(proj: &Project, path: &str) -> String {
+        std::fs::read_to_string(proj.dest_dir.join(path)).unwrap()
+    }
+
+    #[test]
+    fn not_found_page_is_compiled_in_outline_mode() {
+        let files = [
+            (
+                "main.typ",
+                "#set document(title: \"Book\")\n= Intro\nHello\n",
+            ),
+            ("404.typ", "= Lost\nNo such page\n"),
+        ];
+        let args = CompileArgs {
+            entry: Some("main.typ".into()),
+            mode: RenderMode::StaticHtml,
+            ..Default::default()
+        };
+        let proj = build_book("outline-404", &files, args);
+        let page = read_page(&proj, NOT_FOUND_PAGE);
+        std::fs::remove_dir_all(&proj.args.dir).unwrap();
+
+        assert!(page.contains("No such page"));
+        assert!(!page.contains("Hello"));
+    }
+
+    #[test]
+    fn tag_pages_are_compiled_in_outline_mode() {
+        let files = [
+            (
+                "main.typ",
+                "#metadata((tags: (\"guide\",))) <shiroa-page-meta>\n\
+                #set document(title: \"Book\")\n\
+                = Intro\nHello\n",
+            ),
+            // The paged modules are laid out without the inputs of the page.
+            (
+                "tags.typ",
+                "#let tags = json(bytes(sys.inputs.at(\"x-tags\", default: \"[]\")))\n\
+                #for tag in tags [Tagged #tag.name]\n",
+            ),
+        ];
+        let args = CompileArgs {
+            entry: Some("main.typ".into()),
+            ..Default::default()
+        };
+        let proj = build_book("outline-tags", &files, args);
+        let page = read_page(&proj, "tags/guide.html");
+        std::fs::remove_dir_all(&proj.args.dir).unwrap();
+
+        assert!(page.contains("Tagged guide"));
+        assert!(!page.contains("Hello"));
+    }
+
+    #[test]
+    fn social_images_are_rendered_for_chapters() {
+        let book = book_meta(&["intro.typ"], "\"social-image\": true");
+        let files = [
+            ("book.typ", book.as_str()),
+            (
+                "intro.typ",
+                "#metadata((tags: (\"guide\",))) <shiroa-page-meta>\n= Intro\nHello\n",
+            ),
+            ("tags.typ", "= Tags\n"),
+            ("404.typ", "= Lost\n"),
+        ];
+        let args = CompileArgs {
+            mode: RenderMode::StaticHtml,
+            ..Default::default()
+        };
+        let proj = build_book("social-images", &files, args);
+        let dest = proj.dest_dir.clone();
+        let images = ["intro.png", "tags/index.png", "tags/guide.png", "404.png"]
+            .map(|path| dest.join(path).exists());
+        std::fs::remove_dir_all(&proj.args.dir).unwrap();
+
+        assert_eq!(images, [true, false, false, false]);
+    }
+
+    #[test]
+    fn page_drafts_are_hidden_from_the_book() {
+        let book = book_meta(&["intro.typ", "usage.typ"], "");
+        let files = [
+            ("book.typ", book.as_str()),
+            (
+                "intro.typ",
+                "#metadata((draft: true)) <shiroa-page-meta>\n= Intro\n",
+            ),
+            (
+                "usage.typ",
+                "= Usage\nDrafts: #sys.inputs.at(\"x-page-drafts\", default: ()).join()\n",
+            ),
+        ];
+        let args = CompileArgs {
+            mode: RenderMode::StaticHtml,
+            ..Default::default()
+        };
+        let proj = build_book("page-drafts", &files, args);
+        let intro = proj.dest_dir.join("intro.html").exists();
+        let index = read_page(&proj, "index.html");
+        let drafts = proj.tr.ctx.page_drafts.iter().cloned().collect::<Vec<_>>();
+        std::fs::remove_dir_all(&proj.args.dir).unwrap();
+
+        assert!(!intro);
+        assert_eq!(drafts, ["intro.typ"]);
+        // The first published chapter is the index page, which is rendered
+        // again without the draft.
+        assert!(index.contains("Drafts: intro.typ"));
+    }
+
+    #[test]
+    fn page_titles_and_weights_are_applied_to_the_book() {
+        let book = book_meta(&["intro.typ", "usage.typ", "setup.typ"], "");
+        let files = [
+            ("book.typ", book.as_str()),
+            (
+                "intro.typ",
+                "= Intro\nPages: #sys.inputs.at(\"x-page-summary\", default: \"\")\n",
+            ),
+            (
+                "usage.typ",
+                "#metadata((weight: 2)) <shiroa-page-meta>\n= Usage\n",
+            ),
+            (
+                "setup.typ",
+                "#metadata((title: \"Setup\", weight: 1)) <shiroa-page-meta>\n= Install\n",
+            ),
+        ];
+        let args = CompileArgs {
+            mode: RenderMode::StaticHtml,
+            ..Default::default()
+        };
+        let proj = build_book("page-summary", &files, args);
+        let chapters = (proj.chapters.iter())
+            .map(|ch| (ch.path.as_deref().unwrap().to_owned(), ch.title.to_string()))
+            .collect::<Vec<_>>();
+        let intro = read_page(&proj, "intro.html");
+        let index = read_page(&proj, "index.html");
+        std::fs::remove_dir_all(&proj.args.dir).unwrap();
+
+        let chapter = |path: &str, title: &str| (path.to_owned(), title.to_owned());
+        assert_eq!(
+            chapters,
+            [
+                chapter("setup.typ", "Setup"),
+                chapter("usage.typ", "usage.typ"),
+                chapter("intro.typ", "intro.typ"),
+            ]
+        );
+        assert!(index.contains("Install"));
+        // The pages are rendered again with the titles and weights.
+        let pages =
+            r#"{"setup.typ":{"title":"Setup","weight":1},"usage.typ":{"title":null,"weight":2}}"#;
+        assert!(intro.contains(pages), "{intro}");
+    }
 }
@@ -1,42 +1,60 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use reflexo_typst::CompilerExt;
+use reflexo_typst::{CompilerExt, TypstDocument};
 use serde::{Deserialize, Serialize};
-use typst::foundations::Content;
+use typst::{
+    foundations::Content,
+    layout::{Frame, FrameItem},
+    text::Lang,
+};
 
 use crate::{
     args::MetaSource,
     book::meta::{BookMeta, BuildMeta},
     error::prelude::*,
     project::Project,
-    utils::UnwrapOrExit,
+    render::{EmbedPolicy, FRAME_TAGS, MEDIA_TAGS},
+    tui_warn,
     version::SHIROA_PACKAGE_VERSION,
 };
 
 impl Project {
     pub(super) fn build_meta(&mut self) -> Result<()> {
-        let args = &self.args;
-        let meta_source = args.meta_source;
-        let mut final_dest_dir = args.dest_dir.clone();
-
-        let dir = Path::new(&args.dir);
-        let mut entry_file = None;
-        if dir.is_file() {
-            if meta_source == MetaSource::Strict {
-                return Err(error_once!("project dir is a file", dir: dir.display()));
+        let mut final_dest_dir = self.args.dest_dir.clone();
+        let entry_file = self.args.entry.clone();
+
+        match self.meta_source {
+            MetaSource::Strict => self.compile_meta()?,
+            MetaSource::Outline => {
+                let entry = entry_file.as_deref();
+                self.compile_entry_meta(entry.context("no entry file in outline mode")?)?;
             }
-            entry_file = Some(dir.to_owned());
-        }
-
-        if matches!(self.meta_source, MetaSource::Strict) {
-            assert!(entry_file.is_none());
-            self.compile_meta()?;
         }
 
-        if final_dest_dir.is_empty() {
-            if let Some(build_meta) = self.build_meta.as_ref() {
+        if let Some(build_meta) = self.build_meta.as_ref() {
+            if final_dest_dir.is_empty() {
                 final_dest_dir = build_meta.dest_dir.clone();
             }
+
+            let site_url = &build_meta.site_url;
+            self.tr.ctx.site_url = (!site_url.is_empty()).then(|| site_url.as_str().into());
+            // The chapters split by outline have no first page of their own,
+            // but they can still get the images by the card template.
+            let strict = self.meta_source == MetaSource::Strict;
+            self.tr.ctx.social_image = build_meta.social_image && (strict || self.has_og_card());
+
+            let url_source = self.args.allowed_url_source.as_deref();
+            let embed_policy = EmbedPolicy::new(build_meta.embed.as_ref(), url_source)?;
+            let embedding_tags = [FRAME_TAGS, MEDIA_TAGS].concat();
+            if build_meta.csp && embed_policy.has_unlisted_hosts(&embedding_tags) {
+                tui_warn!(
+                    "csp allows the embedded frames and media from any host, as their hosts are checked by --allowed-url-source"
+                );
+            }
+            self.tr.set_xcommands(embed_policy, &build_meta.xcommands)?;
         }
         if final_dest_dir.is_empty() {
             "dist".clone_into(&mut final_dest_dir);
@@ -46,8 +64,8 @@ impl Project {
         self.dest_dir.clone_from(&self.tr.ctx.dest_dir);
 
         if matches!(self.meta_source, MetaSource::Outline) {
-            assert!(entry_file.is_some());
-            self.infer_meta_by_outline(entry_file.unwrap())?;
+            let entry = entry_file.context("no entry file in outline mode")?;
+            self.infer_meta_by_outline(entry)?;
         }
 
         Ok(())
@@ -93,8 +111,8 @@ impl Project {
         Ok(())
     }
 
-    fn query_meta<T: for<'a> serde::Deserialize<'a>>(
-        &mut self,
+    pub(super) fn query_meta<T: for<'a> serde::Deserialize<'a>>(
+        &self,
         item: &str,
         f: impl FnOnce(&str) -> Result<Vec<Content>>,
     ) -> Result<Option<T>> {
@@ -105,7 +123,7 @@ impl Project {
     }
 
     fn query_meta_<T: for<'a> serde::Deserialize<'a>>(
-        &mut self,
+        &self,
         item: &str,
         f: impl FnOnce(&str) -> Result<Vec<Content>>,
     ) -> Result<Option<T>> {
@@ -119,36 +137,106 @@ impl Project {
             serde_json::from_value(res).context("cannot convert metadata item(s)")?;
 
         if res.len() > 1 {
-            bail!("multiple metadata items in book.typ");
+            bail!("multiple metadata items");
         }
 
         Ok(res.into_iter().next().map(|v| v.value))
     }
 
-    fn infer_meta_by_outline(&mut self, entry: PathBuf) -> Result<()> {
+    fn relative_entry<'a>(&self, entry: &'a Path) -> Result<&'a Path> {
+        let root = &self.tr.ctx.root_dir;
+        entry.strip_prefix(root).map_err(|_| {
+            error_once!(
+                "entry file must be in the root directory",
+                entry: entry.display(),
+                root: root.display(),
+            )
+        })
+    }
+
+    /// Retrieves the book meta from the main file in outline mode. The
+    /// manual-set `<shiroa-book-meta>` and `<shiroa-build-meta>` are used
+    /// first, and the missing fields are inferred from the document.
+    fn compile_entry_meta(&mut self, entry: &Path) -> Result<()> {
         // println!("entry = {:?}, root = {:?}", entry, self.tr.root_dir);
-        let entry = entry.strip_prefix(&self.tr.ctx.root_dir).unwrap_or_exit();
+        let entry = self.relative_entry(entry)?;
         let (task, doc) = self.tr.compile_book(entry)?;
 
-        // let outline = crate::outline::outline(&doc);
-        // println!("outline: {:#?}", outline);
+        let g = &task.graph;
+        let query = |item: &str| {
+            let res = g.query(item.to_string(), &doc);
+            task.report(res).context("cannot retrieve metadata item(s)")
+        };
 
-        let chapters = self.tr.compile_pages_by_outline(entry)?;
-        self.chapters = self.generate_chapters(&chapters);
+        let mut book_meta = self
+            .query_meta::<BookMeta>("<shiroa-book-meta>", query)?
+            .unwrap_or_default();
+        self.build_meta = self.query_meta::<BuildMeta>("<shiroa-build-meta>", query)?;
 
         let info = &doc.info();
-        let title = info.title.as_ref().map(|t| t.as_str());
-        let authors = info.author.iter().map(|a| a.as_str().to_owned()).collect();
-
-        self.book_meta = BookMeta {
-            title: title.unwrap_or("Typst Document").to_owned(),
-            authors,
-            language: "en".to_owned(),
-            summary: chapters,
-            ..Default::default()
-        };
+        if book_meta.title.is_empty() {
+            let title = info.title.as_ref().map(|t| t.as_str());
+            book_meta.title = title.unwrap_or("Typst Document").to_owned();
+        }
+        if book_meta.authors.is_empty() {
+            book_meta.authors = info.author.iter().map(|a| a.as_str().to_owned()).collect();
+        }
+        if book_meta.description.is_empty() {
+            if let Some(description) = &info.description {
+                book_meta.description = description.as_str().to_owned();
+            }
+        }
+        if book_meta.language.is_empty() {
+            book_meta.language = document_lang(&doc).unwrap_or_else(|| "en".to_owned());
+        }
+        self.book_meta = book_meta;
 
         self.tr.ctx = task.ctx;
         Ok(())
     }
+
+    fn infer_meta_by_outline(&mut self, entry: PathBuf) -> Result<()> {
+        let entry = self.relative_entry(&entry)?;
+
+        // let outline = crate::outline::outline(&doc);
+        // println!("outline: {:#?}", outline);
+
+        // The summary is always inferred, since the chapters are split from
+        // the main file.
+        let outline = self.tr.compile_pages_by_outline(entry)?;
+        self.chapters = self.generate_chapters(&outline.summary);
+        self.book_meta.summary = outline.summary;
+        self.outline_entry = Some(entry.to_owned());
+        self.outline_pages = outline.static_pages;
+
+        Ok(())
+    }
+}
+
+/// Detects the main language of the document, i.e. the language of most text,
+/// which is set by `set text(lang: ..)`.
+fn document_lang(doc: &TypstDocument) -> Option<String> {
+    fn count_langs(frame: &Frame, langs: &mut HashMap<Lang, usize>) {
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => count_langs(&group.frame, langs),
+                FrameItem::Text(text) => *langs.entry(text.lang).or_default() += text.glyphs.len(),
+                _ => {}
+            }
+        }
+    }
+
+    let TypstDocument::Paged(doc) = doc else {
+        return None;
+    };
+
+    let mut langs = HashMap::new();
+    for page in doc.pages() {
+        count_langs(&page.frame, &mut langs);
+    }
+
+    langs
+        .into_iter()
+        .max_by(|(a, m), (b, n)| m.cmp(n).then_with(|| b.as_str().cmp(a.as_str())))
+        .map(|(lang, _)| lang.as_str().to_owned())
 }
@@ -0,0 +1,185 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use reflexo_typst::escape::{escape_str, AttributeEscapes};
+
+use crate::{error::prelude::*, project::Project, tui_warn};
+
+/// The redirects of the moved pages for static hosts, e.g. Netlify and
+/// Cloudflare Pages, in the dest directory.
+const REDIRECTS_FILE: &str = "_redirects";
+
+impl Project {
+    /// Writes the redirects declared by `redirects` in build-meta, as a stub
+    /// page at each old path and in the `_redirects` file. The `_redirects`
+    /// file of the last build is removed if no redirect is declared.
+    pub(super) fn write_redirects(&self) -> Result<()> {
+        let redirects = self.build_meta.as_ref().map(|meta| &meta.redirects);
+        let Some(redirects) = redirects.filter(|redirects| !redirects.is_empty()) else {
+            let path = self.dest_dir.join(REDIRECTS_FILE);
+            if path.is_file() {
+                std::fs::remove_file(&path).map_err(error_once_map!("remove redirects"))?;
+            }
+            return Ok(());
+        };
+
+        let chapters = self
+            .chapters
+            .iter()
+            .filter_map(|ch| ch.path.as_deref())
+            .map(page_path)
+            .collect::<BTreeSet<_>>();
+
+        let url_base = &self.tr.ctx.url_base;
+        let site_url = self
+            .tr
+            .ctx
+            .site_url
+            .as_deref()
+            .map(|url| url.trim_end_matches('/'));
+
+        let written = &self.tr.ctx.written;
+        let mut lines = String::new();
+        for (from, to) in redirects {
+            let from_page = page_path(from);
+            if chapters.contains(&from_page) {
+                tui_warn!("skip the redirect from {from}: it is the path of a chapter");
+                continue;
+            }
+
+            let target = if to.contains("://") {
+                to.clone()
+            } else {
+                let (to, fragment) = match to.split_once('#') {
+                    Some((to, fragment)) => (to, format!("#{fragment}")),
+                    None => (to.as_str(), String::new()),
+                };
+                let to_page = page_path(to);
+                if !chapters.contains(&to_page) {
+                    tui_warn!("the redirect target {to} of {from} is not a chapter");
+                }
+                format!("{url_base}{}{fragment}", page_url(&to_page))
+            };
+
+            // The canonical url must be absolute.
+            let canonical = match site_url {
+                Some(site_url) if target.starts_with('/') => format!("{site_url}{target}"),
+                _ => target.clone(),
+            };
+            let stub = redirect_stub(&target, &canonical);
+            written.write(self.dest_dir.join(&from_page), stub)?;
+
+            lines.push_str(&format!(
+                "{url_base}{} {target} 301\n",
+                page_url(&from_page)
+            ));
+        }
+
+        written.write(self.dest_dir.join(REDIRECTS_FILE), lines)
+    }
+}
+
+/// Reads the redirects written by the last build, mapping the old url paths
+/// to the new urls.
+pub(crate) fn read_redirects(dest_dir: &Path) -> BTreeMap<String, String> {
+    let Ok(content) = std::fs::read_to_string(dest_dir.join(REDIRECTS_FILE)) else {
+        return BTreeMap::new();
+    };
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some((parts.next()?.to_owned(), parts.next()?.to_owned()))
+        })
+        .collect()
+}
+
+/// Resolves a path in the redirects to the page in the dest directory, e.g.
+/// `guide/old.typ` to `guide/old.html` and `guide/` to `guide/index.html`.
+fn page_path(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    if path.is_empty() || path.ends_with('/') {
+        format!("{path}index.html")
+    } else if let Some(stem) = path.strip_suffix(".typ") {
+        format!("{stem}.html")
+    } else if Path::new(path).extension().is_none() {
+        format!("{path}.html")
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Gets the url path of a page, where the `index.html` is served at its
+/// directory.
+fn page_url(page: &str) -> &str {
+    page.strip_suffix("index.html").unwrap_or(page)
+}
+
+fn redirect_stub(target: &str, canonical: &str) -> String {
+    let target = escape_str::<AttributeEscapes>(target);
+    let canonical = escape_str::<AttributeEscapes>(canonical);
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Redirecting...</title>
+<meta http-equiv="refresh" content="0; url={target}">
+<link rel="canonical" href="{canonical}">
+<meta name="robots" content="noindex">
+</head>
+<body>
+<p>Redirecting to <a href="{target}">{target}</a>...</p>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_resolved_to_pages() {
+        assert_eq!(page_path("guide/old.typ"), "guide/old.html");
+        assert_eq!(page_path("/guide/old"), "guide/old.html");
+        assert_eq!(page_path("guide/old.html"), "guide/old.html");
+        assert_eq!(page_path("guide/"), "guide/index.html");
+        assert_eq!(page_path("/"), "index.html");
+        assert_eq!(page_path(""), "index.html");
+        assert_eq!(page_path("slides.pdf"), "slides.pdf");
+    }
+
+    #[test]
+    fn index_pages_are_served_at_directories() {
+        assert_eq!(page_url("guide/index.html"), "guide/");
+        assert_eq!(page_url("index.html"), "");
+        assert_eq!(page_url("guide/old.html"), "guide/old.html");
+    }
+
+    #[test]
+    fn redirects_are_read_from_the_dest_dir() {
+        let dest_dir =
+            std::env::temp_dir().join(format!("shiroa-redirects-{}", std::process::id()));
+        std::fs::create_dir_all(&dest_dir).unwrap();
+        assert!(read_redirects(&dest_dir).is_empty());
+
+        let lines =
+            "/book/old.html /book/new.html 301\n\n/book/ext https://example.com/ 301\nbroken\n";
+        std::fs::write(dest_dir.join(REDIRECTS_FILE), lines).unwrap();
+        let redirects = read_redirects(&dest_dir);
+        std::fs::remove_dir_all(&dest_dir).unwrap();
+
+        assert_eq!(
+            redirects,
+            BTreeMap::from([
+                ("/book/ext".to_owned(), "https://example.com/".to_owned()),
+                ("/book/old.html".to_owned(), "/book/new.html".to_owned()),
+            ])
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::{collections::BTreeSet, path::PathBuf, time::Duration};
+
+use reflexo_typst::ImmutStr;
+use typst::ecow::EcoString;
+
+use crate::{error::prelude::*, tui_error, tui_info, tui_warn};
+
+/// The outcome of compiling a single chapter.
+#[derive(Debug, Clone)]
+pub enum ChapterStatus {
+    /// The chapter is compiled and written to the output directory.
+    Success,
+    /// The chapter failed to compile, with the rendered error message.
+    Failed(EcoString),
+}
+
+/// A per-chapter entry of [`BuildReport`].
+#[derive(Debug, Clone)]
+pub struct ChapterReport {
+    /// The path to the chapter, relative to the book's root directory.
+    pub path: ImmutStr,
+    pub status: ChapterStatus,
+    pub elapsed: Duration,
+}
+
+/// Collects the result of each chapter compiled in a build.
+#[derive(Debug, Default, Clone)]
+pub struct BuildReport {
+    pub chapters: Vec<ChapterReport>,
+    /// The files written to the dest directory by the build, including the
+    /// files that are skipped because their content is unchanged.
+    pub outputs: BTreeSet<PathBuf>,
+}
+
+impl BuildReport {
+    pub fn failures(&self) -> impl Iterator<Item = (&ImmutStr, &EcoString)> {
+        self.chapters.iter().filter_map(|ch| match &ch.status {
+            ChapterStatus::Failed(err) => Some((&ch.path, err)),
+            ChapterStatus::Success => None,
+        })
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.failures().next().is_some()
+    }
+
+    /// Prints a summary of the build to the terminal.
+    pub fn summarize(&self) {
+        let failed = self.failures().count();
+        let total = self.chapters.len();
+        if failed == 0 {
+            tui_info!(h "Built", "{total} chapter(s)");
+            return;
+        }
+
+        tui_error!(h "Failed", "{failed} of {total} chapter(s) failed to compile");
+        for (path, err) in self.failures() {
+            tui_error!(h "Error", "{path}: {err}");
+        }
+    }
+
+    /// Finishes the build. Unless `keep_going` is set, a build with any failed
+    /// chapter is an error.
+    pub fn finish(&self, keep_going: bool) -> Result<()> {
+        self.summarize();
+
+        let failed = self.failures().count();
+        if failed == 0 {
+            return Ok(());
+        }
+        if keep_going {
+            tui_warn!("error pages are written for the failed chapter(s) because of --keep-going");
+            return Ok(());
+        }
+
+        Err(error_once!("failed to build the book", failed_chapters: failed))
+    }
+}
@@ -1,13 +1,25 @@
-use std::{collections::BTreeMap, net::SocketAddr, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use reflexo_typst::{
     path::unix_slash,
     vfs::{notify::NotifyMessage, FilesystemEvent, FsProvider},
     watch_deps, ImmutStr, TypstSystemWorld, WorldDeps,
 };
+use serde::Serialize;
 use tokio::sync::{broadcast, mpsc};
 
-use crate::{project::Project, render::SearchRenderer, tui, tui_hint, tui_info};
+use crate::{
+    error::prelude::*,
+    project::{read_redirects, BuildReport, ChapterStatus, Project},
+    render::SearchRenderer,
+    tui, tui_error, tui_hint, tui_info,
+    utils::open_browser,
+};
 
 impl Project {
     pub(crate) async fn watch(
@@ -15,9 +27,11 @@ impl Project {
         // active_set: Arc<Mutex<HashMap<ImmutStr, usize>>>,
         mut hb_rx: mpsc::UnboundedReceiver<ServeEvent>,
         tx: broadcast::Sender<WatchSignal>,
-        addr: Option<SocketAddr>,
+        status: Arc<Mutex<WatchStatus>>,
+        url: Option<String>,
+        open: bool,
     ) {
-        let _ = self.build();
+        self.report_build(&status, true, |proj| proj.build());
         let (dep_tx, dep_rx) = mpsc::unbounded_channel();
         let (fs_tx, mut fs_rx) = mpsc::unbounded_channel();
         tokio::spawn(watch_deps(dep_rx, move |event| {
@@ -43,8 +57,8 @@ impl Project {
                 world.evict_vfs(60);
             }
 
-            if let Some(addr) = &addr {
-                tui_hint!("Server started at http://{addr}");
+            if let Some(url) = &url {
+                tui_hint!("Server started at {url}");
             }
         };
 
@@ -52,6 +66,9 @@ impl Project {
         let mut world = snap.world.clone();
         // first report.
         finish(&mut world);
+        if let Some(url) = url.as_deref().filter(|_| open) {
+            open_browser(url);
+        }
 
         let mut active_files: BTreeMap<ImmutStr, usize> = BTreeMap::new();
         loop {
@@ -73,6 +90,7 @@ impl Project {
             // todo: reset_snapshot looks not good
 
             let is_heartbeat = matches!(event, WatchEvent::Serve(ServeEvent::HoldPath(..)));
+            let mut rebuild = false;
             match event {
                 WatchEvent::Fs(event) => {
                     self.tr.reset_snapshot();
@@ -81,7 +99,19 @@ impl Project {
                     });
 
                     let _ = tui::clear();
-                    let _ = self.build_meta();
+                    self.build_meta_blocking();
+
+                    snap = self.tr.snapshot();
+                    world = snap.world.clone();
+                }
+                WatchEvent::Serve(ServeEvent::Rebuild) => {
+                    // The files may be changed without notifying the watcher.
+                    self.tr.reset_snapshot();
+
+                    let _ = tui::clear();
+                    tui_info!("Rebuilding the book...");
+                    self.build_meta_blocking();
+                    rebuild = true;
 
                     snap = self.tr.snapshot();
                     world = snap.world.clone();
@@ -129,15 +159,29 @@ impl Project {
                         continue;
                     }
 
+                    status.lock().unwrap().active_files = active_files
+                        .iter()
+                        .map(|(path, count)| (path.to_string(), *count))
+                        .collect();
+
                     let _ = tui::clear();
                     tui_info!("Recompiling changed chapters: {active_files:?}");
 
-                    let _ = self.build_meta();
+                    self.build_meta_blocking();
                 }
             }
 
-            // todo: blocking?
-            let _ = self.compile_once(&active_files, SearchRenderer::new());
+            // A full build compiles all of the chapters.
+            let no_active_files = BTreeMap::new();
+            let compiled = if rebuild {
+                &no_active_files
+            } else {
+                &active_files
+            };
+            let full = compiled.is_empty();
+            self.report_build(&status, full, |proj| {
+                proj.compile_once(compiled, SearchRenderer::new())
+            });
 
             if !is_heartbeat {
                 let _ = tx.send(WatchSignal::Reload);
@@ -145,14 +189,133 @@ impl Project {
             finish(&mut world);
         }
     }
+
+    /// Builds the book meta in place, which hands the other tasks of the worker
+    /// thread to the other workers, so that the server stays responsive. It
+    /// requires the multi-thread runtime started by `async_continue`.
+    fn build_meta_blocking(&mut self) {
+        if let Err(err) = tokio::task::block_in_place(|| self.build_meta()) {
+            tui_error!("failed to build book meta: {err}");
+        }
+    }
+
+    /// Reports the result of a build without stopping the watch loop. The
+    /// build runs in place as [`Self::build_meta_blocking`] does.
+    fn report_build(
+        &mut self,
+        status: &Mutex<WatchStatus>,
+        full: bool,
+        f: impl FnOnce(&mut Self) -> Result<BuildReport>,
+    ) {
+        status.lock().unwrap().building = true;
+        let start = Instant::now();
+        let result = tokio::task::block_in_place(|| f(self));
+        let elapsed = start.elapsed();
+
+        let timings = match result {
+            Ok(report) => {
+                report.summarize();
+                BuildTimings::new(full, elapsed, Some(&report), None)
+            }
+            Err(err) => {
+                tui_error!("failed to build the book: {err}");
+                BuildTimings::new(full, elapsed, None, Some(err.to_string()))
+            }
+        };
+
+        let mut status = status.lock().unwrap();
+        status.building = false;
+        status.last_build = Some(timings);
+        // Only the full builds write the redirects.
+        if full {
+            status.redirects = read_redirects(&self.dest_dir);
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ServeEvent {
     HoldPath(ImmutStr, bool),
+    /// Rebuilds the whole book, e.g. by the `__shiroa/rebuild` endpoint.
+    Rebuild,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WatchSignal {
     Reload,
+    /// The server is shutting down, which closes the live reload streams.
+    Shutdown,
+}
+
+/// The state of the watch loop, which is inspected by the `__shiroa/status`
+/// endpoint.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchStatus {
+    /// Whether a build is running.
+    pub building: bool,
+    /// The chapters opened in the browsers, with the number of the pages
+    /// holding them. Only these chapters are recompiled on changes.
+    pub active_files: BTreeMap<String, usize>,
+    /// The timings of the last finished build.
+    pub last_build: Option<BuildTimings>,
+    /// The redirects written by the last full build, which are served by the
+    /// dev server.
+    #[serde(skip)]
+    pub redirects: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildTimings {
+    /// Whether all of the chapters are compiled.
+    pub full: bool,
+    /// The unix time in milliseconds when the build finished.
+    pub finished_at: u128,
+    pub elapsed_ms: u128,
+    /// The error that stopped the build.
+    pub error: Option<String>,
+    pub chapters: Vec<ChapterTimings>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterTimings {
+    pub path: String,
+    pub elapsed_ms: u128,
+    /// The error message if the chapter failed to compile.
+    pub error: Option<String>,
+}
+
+impl BuildTimings {
+    fn new(
+        full: bool,
+        elapsed: Duration,
+        report: Option<&BuildReport>,
+        error: Option<String>,
+    ) -> Self {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis());
+        let chapters = report
+            .into_iter()
+            .flat_map(|report| &report.chapters)
+            .map(|ch| ChapterTimings {
+                path: ch.path.to_string(),
+                elapsed_ms: ch.elapsed.as_millis(),
+                error: match &ch.status {
+                    ChapterStatus::Failed(err) => Some(err.to_string()),
+                    ChapterStatus::Success => None,
+                },
+            })
+            .collect();
+
+        Self {
+            full,
+            finished_at,
+            elapsed_ms: elapsed.as_millis(),
+            error,
+            chapters,
+        }
+    }
 }
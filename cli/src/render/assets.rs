@@ -0,0 +1,115 @@
+//! The internal assets copied to the `internal` directory of the book. They
+//! are fingerprinted by their content, so that a CDN never serves a stale
+//! renderer after upgrading shiroa.
+
+use std::{collections::BTreeMap, path::Path, sync::OnceLock};
+
+/// The file name of the asset manifest in the dest directory, which maps the
+/// original names of the internal assets to their fingerprinted names.
+pub const ASSET_MANIFEST: &str = "asset-manifest.json";
+
+/// An internal asset embedded in the binary.
+pub struct InternalAsset {
+    /// The original file name, e.g. `shiroa.js`.
+    pub name: &'static str,
+    pub content: &'static [u8],
+    /// Whether the asset is only used by the search.
+    pub search: bool,
+}
+
+pub const INTERNAL_ASSETS: [InternalAsset; 6] = [
+    InternalAsset {
+        name: "typst_ts_renderer_bg.wasm",
+        content: include_bytes!("../../../assets/artifacts/typst_ts_renderer_bg.wasm"),
+        search: false,
+    },
+    InternalAsset {
+        name: "svg_utils.js",
+        content: include_bytes!("../../../assets/artifacts/svg_utils.js"),
+        search: false,
+    },
+    InternalAsset {
+        name: "shiroa.js",
+        content: include_bytes!("../../../assets/artifacts/shiroa.js"),
+        search: false,
+    },
+    InternalAsset {
+        name: "searcher.js",
+        content: include_bytes!("../../../assets/artifacts/searcher.js"),
+        search: true,
+    },
+    InternalAsset {
+        name: "mark.min.js",
+        content: include_bytes!("../../../assets/artifacts/mark.min.js"),
+        search: true,
+    },
+    InternalAsset {
+        name: "elasticlunr.min.js",
+        content: include_bytes!("../../../assets/artifacts/elasticlunr.min.js"),
+        search: true,
+    },
+];
+
+/// Maps the original file names of the internal assets to their fingerprinted
+/// names, e.g. `shiroa.js` to `shiroa.0123456789abcdef.js`.
+pub fn asset_manifest() -> &'static BTreeMap<&'static str, String> {
+    static MANIFEST: OnceLock<BTreeMap<&'static str, String>> = OnceLock::new();
+    MANIFEST.get_or_init(|| {
+        INTERNAL_ASSETS
+            .iter()
+            .map(|asset| (asset.name, fingerprint(asset.name, asset.content)))
+            .collect()
+    })
+}
+
+/// Whether the file is a fingerprinted internal asset, which never changes
+/// and can be cached forever.
+pub fn is_fingerprinted_asset(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    asset_manifest().values().any(|asset| asset == name)
+}
+
+fn fingerprint(name: &str, content: &[u8]) -> String {
+    let hash = typst::utils::hash128(content) as u64;
+    match name.split_once('.') {
+        Some((stem, ext)) => format!("{stem}.{hash:016x}.{ext}"),
+        None => format!("{name}.{hash:016x}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_are_inserted_after_stems() {
+        let name = fingerprint("shiroa.js", b"a");
+        let (stem, rest) = name.split_once('.').unwrap();
+        let (hash, ext) = rest.split_once('.').unwrap();
+        assert_eq!((stem, ext), ("shiroa", "js"));
+        assert_eq!(hash.len(), 16);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+        assert!(fingerprint("mark.min.js", b"a").ends_with(".min.js"));
+        assert!(fingerprint("LICENSE", b"a").starts_with("LICENSE."));
+        assert_eq!(fingerprint("shiroa.js", b"a"), name);
+        assert_ne!(fingerprint("shiroa.js", b"b"), name);
+    }
+
+    #[test]
+    fn fingerprinted_assets_are_recognized_by_names() {
+        let manifest = asset_manifest();
+        assert_eq!(manifest.len(), INTERNAL_ASSETS.len());
+
+        let shiroa = &manifest["shiroa.js"];
+        assert!(is_fingerprinted_asset(
+            &Path::new("book/internal").join(shiroa)
+        ));
+        assert!(!is_fingerprinted_asset(Path::new(
+            "book/internal/shiroa.js"
+        )));
+        assert!(!is_fingerprinted_asset(Path::new("book/index.html")));
+    }
+}
@@ -0,0 +1,380 @@
+//! Computes the content security policy of the built pages, which is emitted
+//! as a `<meta http-equiv>` in each page and as a `_headers` file for static
+//! hosts.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use base64::Engine;
+use reflexo_typst::escape::{escape_str, AttributeEscapes};
+use sha2::{Digest, Sha256};
+
+use crate::render::{
+    html_parse::{parse_elements, Element},
+    EmbedPolicy,
+};
+
+/// The file listing the headers of each page for static hosts, e.g. Netlify
+/// and Cloudflare Pages.
+pub const HEADERS_FILE: &str = "_headers";
+
+const CSP_HEADER: &str = "Content-Security-Policy";
+/// The embedded tags allowed by `frame-src` and `media-src`.
+pub(crate) const FRAME_TAGS: [&str; 2] = ["iframe", "frame"];
+pub(crate) const MEDIA_TAGS: [&str; 2] = ["audio", "video"];
+
+/// A content security policy, mapping each directive to its sources.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ContentSecurityPolicy(BTreeMap<String, BTreeSet<String>>);
+
+impl ContentSecurityPolicy {
+    /// Computes the strict policy of a page. The inline scripts are allowed by
+    /// their hashes, and the embedded frames and media by the embed policy.
+    /// `wasm` allows compiling the renderer module in the dyn-paged modes.
+    ///
+    /// The policy is not strict for styles, which allows `'unsafe-inline'`,
+    /// since the typst html output and the themes set the styles of elements
+    /// by their `style` attributes.
+    pub fn of_page(html: &str, embed: &EmbedPolicy, wasm: bool) -> Self {
+        let mut csp = Self::default();
+        csp.add("default-src", "'self'");
+        csp.add("script-src", "'self'");
+        if wasm {
+            csp.add("script-src", "'wasm-unsafe-eval'");
+            csp.add("img-src", "blob:");
+        }
+        for source in ["'self'", "'unsafe-inline'", "data:"] {
+            csp.add("style-src", source);
+        }
+        for directive in ["img-src", "font-src"] {
+            csp.add(directive, "'self'");
+            csp.add(directive, "data:");
+        }
+        csp.add("connect-src", "'self'");
+        csp.add("media-src", "'self'");
+        csp.add("object-src", "'none'");
+        csp.add("base-uri", "'self'");
+
+        // The embedded elements are derived from the embed policy rather than
+        // the page, since they are in the paged modules in the dyn-paged modes.
+        for source in embed.allowed_sources(&FRAME_TAGS) {
+            csp.add("frame-src", &source);
+        }
+        for source in embed.allowed_sources(&MEDIA_TAGS) {
+            csp.add("media-src", &source);
+        }
+
+        PageScan {
+            html,
+            csp: &mut csp,
+        }
+        .elements(&parse_elements(html));
+
+        if !csp.0.contains_key("frame-src") {
+            csp.add("frame-src", "'none'");
+        }
+        csp
+    }
+
+    pub fn add(&mut self, directive: &str, source: &str) {
+        let sources = self.0.entry(directive.to_owned()).or_default();
+        sources.insert(source.to_owned());
+    }
+
+    /// Allows the inline scripts in a html snippet, e.g. the live reload
+    /// script injected by `serve`.
+    pub fn allow_inline_scripts(&mut self, html: &str) {
+        PageScan { html, csp: self }.elements(&parse_elements(html));
+    }
+
+    /// Parses a policy, e.g. from the `<meta http-equiv>` of a page.
+    pub fn parse(policy: &str) -> Self {
+        let mut csp = Self::default();
+        for directive in policy.split(';') {
+            let mut parts = directive.split_whitespace();
+            if let Some(name) = parts.next() {
+                let sources = csp.0.entry(name.to_ascii_lowercase()).or_default();
+                sources.extend(parts.map(str::to_owned));
+            }
+        }
+        csp
+    }
+
+    /// Inserts the policy into the `<head>` of a page as a `<meta
+    /// http-equiv>`.
+    pub fn insert_meta(&self, html: &str) -> String {
+        let elems = parse_elements(html);
+        let Some(head) = find_element(&elems, &|elem| elem.tag == "head") else {
+            return html.to_owned();
+        };
+
+        let policy = self.to_string();
+        let policy = escape_str::<AttributeEscapes>(&policy);
+        let meta = format!(r#"<meta http-equiv="{CSP_HEADER}" content="{policy}">"#);
+        let at = head.inner_start;
+        format!("{}{meta}{}", &html[..at], &html[at..])
+    }
+
+    /// Takes the policy out of the `<meta http-equiv>` of a page, returning
+    /// the page without the meta.
+    pub fn take_meta(html: &str) -> Option<(String, Self)> {
+        let elems = parse_elements(html);
+        let meta = find_element(&elems, &|elem| {
+            elem.tag == "meta"
+                && elem
+                    .attrs
+                    .iter()
+                    .any(|(k, v)| k == "http-equiv" && v.eq_ignore_ascii_case(CSP_HEADER))
+        })?;
+
+        let content = meta.attrs.iter().find(|(k, _)| k == "content");
+        let csp = Self::parse(content.map_or("", |(_, v)| v.as_str()));
+        let html = format!("{}{}", &html[..meta.start], &html[meta.end..]);
+        Some((html, csp))
+    }
+
+    /// Renders the `_headers` file, listing the policy of each page by its
+    /// url path.
+    pub fn render_headers(pages: &[(String, Self)]) -> String {
+        let mut headers = String::new();
+        for (path, csp) in pages {
+            headers.push_str(&format!("{path}\n  {CSP_HEADER}: {csp}\n"));
+        }
+        headers
+    }
+}
+
+impl fmt::Display for ContentSecurityPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (directive, sources) in &self.0 {
+            if !first {
+                f.write_str("; ")?;
+            }
+            first = false;
+
+            f.write_str(directive)?;
+            for source in sources {
+                write!(f, " {source}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects the sources used by a page into its policy.
+struct PageScan<'a> {
+    html: &'a str,
+    csp: &'a mut ContentSecurityPolicy,
+}
+
+impl PageScan<'_> {
+    fn elements(&mut self, elems: &[Element]) {
+        for elem in elems {
+            self.element(elem);
+            self.elements(&elem.children);
+        }
+    }
+
+    fn element(&mut self, elem: &Element) {
+        let attr = |name: &str| {
+            let attr = elem.attrs.iter().find(|(k, _)| k == name);
+            attr.map(|(_, v)| v.as_str())
+        };
+
+        // The event handler attributes are allowed by their hashes.
+        for (k, v) in &elem.attrs {
+            if k.starts_with("on") {
+                self.csp.add("script-src", "'unsafe-hashes'");
+                self.csp.add("script-src", &hash_source(v));
+            }
+        }
+
+        let directive = match elem.tag.as_str() {
+            "script" => match attr("src") {
+                Some(_) => "script-src",
+                None => {
+                    let script = &self.html[elem.inner_start..elem.inner_end];
+                    self.csp.add("script-src", &hash_source(script));
+                    return;
+                }
+            },
+            "link" if attr("rel") == Some("stylesheet") => "style-src",
+            "img" => "img-src",
+            _ => return,
+        };
+
+        let src = attr("src").or_else(|| attr("href")).unwrap_or_default();
+        if let Some(source) = url_source(src) {
+            self.csp.add(directive, &source);
+        }
+    }
+}
+
+/// Gets the source of an url to allow, or `None` if it is a relative url,
+/// which is allowed by `'self'`. A scheme-relative url, e.g. `//cdn.net/a.js`,
+/// is allowed by its host in any scheme of the page.
+fn url_source(url: &str) -> Option<String> {
+    if url.starts_with("data:") {
+        return Some("data:".to_owned());
+    }
+
+    let (scheme, url) = match url.strip_prefix("//") {
+        Some(rest) => (None, url::Url::parse(&format!("https://{rest}")).ok()?),
+        None => {
+            let url = url::Url::parse(url).ok()?;
+            (Some(url.scheme().to_owned()), url)
+        }
+    };
+    let host = url.host_str()?;
+    let host = match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_owned(),
+    };
+    Some(match scheme {
+        Some(scheme) => format!("{scheme}://{host}"),
+        None => host,
+    })
+}
+
+fn hash_source(text: &str) -> String {
+    let hash = Sha256::digest(text.as_bytes());
+    let hash = base64::engine::general_purpose::STANDARD.encode(hash);
+    format!("'sha256-{hash}'")
+}
+
+fn find_element<'a>(elems: &'a [Element], pred: &impl Fn(&Element) -> bool) -> Option<&'a Element> {
+    elems.iter().find_map(|elem| {
+        if pred(elem) {
+            Some(elem)
+        } else {
+            find_element(&elem.children, pred)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_are_parsed_and_displayed() {
+        let csp = ContentSecurityPolicy::parse(
+            "Default-Src 'self';  script-src 'self' https://a.net ; ;img-src data:",
+        );
+        assert_eq!(
+            csp.to_string(),
+            "default-src 'self'; img-src data:; script-src 'self' https://a.net"
+        );
+        assert_eq!(ContentSecurityPolicy::parse(&csp.to_string()), csp);
+        assert_eq!(ContentSecurityPolicy::parse("").to_string(), "");
+    }
+
+    #[test]
+    fn url_sources_keep_schemes_hosts_and_ports() {
+        let source = |url| url_source(url);
+        assert_eq!(
+            source("https://a.net/x.js?q#f").as_deref(),
+            Some("https://a.net")
+        );
+        assert_eq!(
+            source("http://a.net:8080/x").as_deref(),
+            Some("http://a.net:8080")
+        );
+        assert_eq!(source("//cdn.net/x.js").as_deref(), Some("cdn.net"));
+        assert_eq!(source("data:image/png;base64,AA").as_deref(), Some("data:"));
+        assert_eq!(source("x.js"), None);
+        assert_eq!(source("/x.js"), None);
+        assert_eq!(source("../x.js"), None);
+    }
+
+    #[test]
+    fn hash_sources_are_base64_sha256() {
+        assert_eq!(
+            hash_source("alert(1)"),
+            "'sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI='"
+        );
+    }
+
+    #[test]
+    fn pages_allow_their_inline_scripts_and_sources() {
+        let html = r#"<html><head><script>alert(1)</script><script src="https://cdn.net/a.js"></script>
+            <link rel="stylesheet" href="//fonts.net/a.css"></head>
+            <body><button onclick="go()">go</button><img src="img/a.png">
+            <iframe src="https://player.net/v/1"></iframe>
+            <pre>&lt;script&gt;alert(2)&lt;/script&gt;</pre></body></html>"#;
+        let embed = EmbedPolicy::new(None, None).unwrap();
+        let csp = ContentSecurityPolicy::of_page(html, &embed, false);
+
+        let sources = |directive: &str| csp.0[directive].iter().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            sources("script-src"),
+            [
+                "'self'",
+                "'sha256-5KYv+PUboo5h+0+YAtGRPbwv5d/QxzHslP4YGnUaxRw='",
+                "'sha256-bhHHL3z2vDgxUt0W3dWQOrprscmda2Y5pLsLg4GF+pI='",
+                "'unsafe-hashes'",
+                "https://cdn.net",
+            ]
+        );
+        assert!(csp.0["style-src"].contains("fonts.net"));
+        assert_eq!(sources("img-src"), ["'self'", "data:"]);
+        // No frame is allowed by the builtin policy without hosts.
+        assert_eq!(sources("frame-src"), ["'none'"]);
+    }
+
+    #[test]
+    fn frames_and_media_are_allowed_by_embed_policy() {
+        use crate::book::meta::{EmbedMeta, EmbedTagMeta};
+
+        let listed = EmbedTagMeta {
+            hosts: Some(vec!["player.net".to_owned()]),
+            ..Default::default()
+        };
+        let meta = EmbedMeta {
+            schemes: Some(vec!["https".to_owned()]),
+            tags: Some(BTreeMap::from([
+                ("iframe".to_owned(), listed),
+                ("video".to_owned(), EmbedTagMeta::default()),
+            ])),
+        };
+        // The frames in the page aren't listed, which may be only in the paged
+        // modules.
+        let html =
+            r#"<html><head></head><body><iframe src="https://other.net/v"></iframe></body></html>"#;
+
+        let embed = EmbedPolicy::new(Some(&meta), None).unwrap();
+        let csp = ContentSecurityPolicy::of_page(html, &embed, false);
+        let sources = |directive: &str| csp.0[directive].iter().cloned().collect::<Vec<_>>();
+        assert_eq!(sources("frame-src"), ["https://player.net"]);
+        assert_eq!(sources("media-src"), ["'self'"]);
+
+        let embed = EmbedPolicy::new(Some(&meta), Some("^cdn\\.net$")).unwrap();
+        assert!(!embed.has_unlisted_hosts(&FRAME_TAGS));
+        assert!(embed.has_unlisted_hosts(&MEDIA_TAGS));
+        let csp = ContentSecurityPolicy::of_page(html, &embed, false);
+        let sources = |directive: &str| csp.0[directive].iter().cloned().collect::<Vec<_>>();
+        assert_eq!(sources("frame-src"), ["https://player.net"]);
+        assert_eq!(sources("media-src"), ["'self'", "https:"]);
+    }
+
+    #[test]
+    fn metas_are_inserted_and_taken() {
+        let mut csp = ContentSecurityPolicy::default();
+        csp.add("default-src", "'self'");
+        csp.allow_inline_scripts("<script>alert(1)</script>");
+
+        let html = "<html><head><title>a</title></head><body></body></html>";
+        let with_meta = csp.insert_meta(html);
+        let meta = r#"<html><head><meta http-equiv="Content-Security-Policy" content="default-src"#;
+        assert!(with_meta.starts_with(meta));
+
+        let (page, taken) = ContentSecurityPolicy::take_meta(&with_meta).unwrap();
+        assert_eq!(page, html);
+        assert_eq!(taken, csp);
+        assert!(ContentSecurityPolicy::take_meta(html).is_none());
+        assert_eq!(csp.insert_meta("<p>no head</p>"), "<p>no head</p>");
+    }
+}
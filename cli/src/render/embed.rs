@@ -0,0 +1,283 @@
+//! The policy of the html elements embedded by `shiroa.media`, which are
+//! rendered as raw html in the pages.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use reflexo_typst::escape::{escape_str, AttributeEscapes};
+use typst::{
+    ecow::{eco_format, EcoString},
+    foundations::Regex,
+};
+
+use crate::{
+    book::meta::{EmbedMeta, EmbedTagMeta},
+    error::prelude::*,
+};
+
+/// The tags embedding external content, which are checked wherever they are
+/// in a page, e.g. when created by `html.elem` directly.
+pub(crate) const EMBEDDING_TAGS: [&str; 8] = [
+    "iframe", "frame", "frameset", "embed", "object", "audio", "video", "portal",
+];
+/// The attribute marking the wrapper of an element embedded by `shiroa.media`
+/// in html output.
+pub(crate) const EMBED_MARKER: &str = "data-shiroa-embed";
+
+/// The arguments of an embedded html element.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct EmbedArgs {
+    pub tag: String,
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// The resolved policy of embedded html elements.
+#[derive(Debug, Clone)]
+pub struct EmbedPolicy {
+    schemes: Vec<String>,
+    tags: BTreeMap<String, EmbedTagMeta>,
+    /// The allowed hosts of the tags that don't list their hosts, set by
+    /// `--allowed-url-source`.
+    url_source: Option<Arc<Regex>>,
+}
+
+impl EmbedPolicy {
+    pub fn new(meta: Option<&EmbedMeta>, url_source: Option<&str>) -> Result<Self> {
+        let url_source = match url_source {
+            Some(source) => Some(Arc::new(
+                Regex::new(source).context("invalid allowed url source")?,
+            )),
+            None => None,
+        };
+
+        let meta = meta.cloned().unwrap_or_default();
+        let schemes = meta
+            .schemes
+            .unwrap_or_else(|| vec!["http".to_owned(), "https".to_owned()]);
+        let tags = meta.tags.unwrap_or_else(builtin_tags);
+
+        Ok(Self {
+            schemes,
+            tags,
+            url_source,
+        })
+    }
+
+    /// Checks an embedded element, returning the attributes to render, with
+    /// the forced ones applied, or the violations of the policy.
+    pub fn check(
+        &self,
+        args: &EmbedArgs,
+    ) -> std::result::Result<Vec<(String, String)>, Vec<EcoString>> {
+        let tag = &args.tag;
+        let Some(policy) = self.tags.get(tag) else {
+            return Err(vec![eco_format!("disallowed tag: {tag}")]);
+        };
+
+        let mut violations = vec![];
+        let mut attrs = vec![];
+        for (k, v) in &args.attributes {
+            if policy.force.contains_key(k) {
+                continue;
+            }
+
+            let valid = !k.is_empty() && k.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+            let allowed = k.starts_with("data-") || policy.attributes.contains(k);
+            if !valid || !allowed {
+                violations.push(eco_format!("disallowed attribute: {k} on tag {tag}"));
+                continue;
+            }
+
+            if k == "src" {
+                if let Err(err) = self.check_url(policy, v) {
+                    violations.push(eco_format!("{err}: {v} on tag {tag}"));
+                    continue;
+                }
+            }
+
+            attrs.push((k.clone(), v.clone()));
+        }
+        attrs.extend(policy.force.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+        if violations.is_empty() {
+            Ok(attrs)
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn check_url(&self, policy: &EmbedTagMeta, url: &str) -> std::result::Result<(), &'static str> {
+        let url = url::Url::parse(url).map_err(|_| "invalid source url")?;
+        if !self.schemes.iter().any(|scheme| scheme == url.scheme()) {
+            return Err("disallowed source url scheme");
+        }
+
+        let host = url.host_str().unwrap_or_default();
+        let allowed = match &policy.hosts {
+            Some(hosts) => hosts.iter().any(|pattern| match_host(pattern, host)),
+            None => self.url_source.as_ref().is_some_and(|re| re.is_match(host)),
+        };
+        if !allowed {
+            return Err("disallowed source url");
+        }
+
+        Ok(())
+    }
+
+    /// Gets the sources allowed for the tags, e.g. `https://example.com`. The
+    /// hosts checked by `--allowed-url-source` can't be listed, so any host is
+    /// allowed in the allowed schemes for them, e.g. `https:`.
+    pub fn allowed_sources(&self, tags: &[&str]) -> BTreeSet<String> {
+        let mut sources = BTreeSet::new();
+        for policy in tags.iter().filter_map(|tag| self.tags.get(*tag)) {
+            let schemes = self.schemes.iter();
+            match &policy.hosts {
+                Some(hosts) => {
+                    for host in hosts {
+                        let schemes = schemes.clone();
+                        sources.extend(schemes.map(|scheme| format!("{scheme}://{host}")));
+                    }
+                }
+                None if self.url_source.is_some() => {
+                    sources.extend(schemes.map(|scheme| format!("{scheme}:")));
+                }
+                None => {}
+            }
+        }
+        sources
+    }
+
+    /// Whether the hosts of any of the tags are only checked by
+    /// `--allowed-url-source`, which can't be listed in the content security
+    /// policy.
+    pub fn has_unlisted_hosts(&self, tags: &[&str]) -> bool {
+        let mut policies = tags.iter().filter_map(|tag| self.tags.get(*tag));
+        self.url_source.is_some() && policies.any(|policy| policy.hosts.is_none())
+    }
+
+    /// Renders an embedded element as raw html, if it complies with the
+    /// policy.
+    pub fn render(&self, args: &EmbedArgs) -> Option<String> {
+        let attrs = self.check(args).ok()?;
+
+        let tag = &args.tag;
+        let fallback = match tag.as_str() {
+            "audio" => "audio.",
+            "video" => "video.",
+            _ => "",
+        };
+        Some(format!("{}{fallback}</{tag}>", start_tag(tag, &attrs)))
+    }
+}
+
+pub(crate) fn start_tag(tag: &str, attrs: &[(String, String)]) -> String {
+    let attrs = attrs
+        .iter()
+        .map(|(k, v)| format!(" {k}=\"{}\"", escape_str::<AttributeEscapes>(v)))
+        .collect::<String>();
+    format!("<{tag}{attrs}>")
+}
+
+/// Matches a host against a pattern, where `*.example.com` matches the
+/// subdomains of `example.com`.
+fn match_host(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+fn builtin_tags() -> BTreeMap<String, EmbedTagMeta> {
+    let tag = |name: &str, attributes: &[&str]| {
+        let policy = EmbedTagMeta {
+            attributes: attributes.iter().map(|attr| (*attr).to_owned()).collect(),
+            ..Default::default()
+        };
+        (name.to_owned(), policy)
+    };
+
+    BTreeMap::from_iter([
+        tag(
+            "iframe",
+            &[
+                "id",
+                "class",
+                "src",
+                "allowfullscreen",
+                "scrolling",
+                "framespacing",
+                "frameborder",
+                "border",
+                "width",
+                "height",
+            ],
+        ),
+        tag("div", &["id", "class"]),
+        tag("audio", &["id", "class", "src", "controls"]),
+        tag("video", &["id", "class", "src", "controls"]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(hosts: &[&str]) -> EmbedTagMeta {
+        EmbedTagMeta {
+            hosts: Some(hosts.iter().map(|host| (*host).to_owned()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hosts_are_matched_by_patterns() {
+        assert!(match_host("example.com", "example.com"));
+        assert!(match_host("Example.com", "example.COM"));
+        assert!(!match_host("example.com", "www.example.com"));
+
+        assert!(match_host("*.example.com", "www.example.com"));
+        assert!(match_host("*.example.com", "a.b.example.com"));
+        assert!(!match_host("*.example.com", "example.com"));
+        assert!(!match_host("*.example.com", ".example.com"));
+        assert!(!match_host("*.example.com", "evilexample.com"));
+    }
+
+    #[test]
+    fn urls_are_checked_by_schemes_and_hosts() {
+        let embed = EmbedPolicy::new(None, None).unwrap();
+        let listed = hosts(&["*.youtube.com", "vimeo.com"]);
+        assert_eq!(
+            embed.check_url(&listed, "https://www.youtube.com/e/1"),
+            Ok(())
+        );
+        assert_eq!(embed.check_url(&listed, "http://vimeo.com/1"), Ok(()));
+        assert_eq!(
+            embed.check_url(&listed, "https://youtube.com.evil.net/"),
+            Err("disallowed source url")
+        );
+        assert_eq!(
+            embed.check_url(&listed, "javascript:alert(1)"),
+            Err("disallowed source url scheme")
+        );
+        assert_eq!(
+            embed.check_url(&listed, "//vimeo.com/1"),
+            Err("invalid source url")
+        );
+
+        // The hosts of the unlisted tags are checked by `--allowed-url-source`.
+        let unlisted = EmbedTagMeta::default();
+        assert!(embed.check_url(&unlisted, "https://vimeo.com/1").is_err());
+        let embed = EmbedPolicy::new(None, Some(r"^player\.bilibili\.com$")).unwrap();
+        assert_eq!(
+            embed.check_url(&unlisted, "https://player.bilibili.com/1"),
+            Ok(())
+        );
+        assert!(embed
+            .check_url(&unlisted, "https://bilibili.com/1")
+            .is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! A tolerant parser of the html output of pages, which locates the elements
+//! by byte offsets to edit the output in place.
+
+/// Elements that have no closing tag.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+/// Elements whose content is not parsed as html.
+const RAW_TEXT_ELEMENTS: [&str; 4] = ["script", "style", "textarea", "title"];
+
+/// An element in a html document, located by byte offsets.
+#[derive(Debug)]
+pub(crate) struct Element {
+    pub tag: String,
+    /// The attributes, with the character references in values decoded.
+    pub attrs: Vec<(String, String)>,
+    pub start: usize,
+    pub inner_start: usize,
+    pub inner_end: usize,
+    /// The end of the element, after its closing tag if any.
+    pub end: usize,
+    pub children: Vec<Element>,
+}
+
+/// Parses the element tree of a html document. The parser is tolerant, which
+/// closes the unclosed elements at the end of their parents.
+pub(crate) fn parse_elements(html: &str) -> Vec<Element> {
+    let bytes = html.as_bytes();
+    let mut roots = vec![];
+    let mut stack: Vec<Element> = vec![];
+
+    fn push(stack: &mut [Element], roots: &mut Vec<Element>, elem: Element) {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(elem),
+            None => roots.push(elem),
+        }
+    }
+
+    let find_from = |pat: &str, from: usize| html[from..].find(pat).map(|idx| idx + from);
+
+    let mut pos = 0;
+    while let Some(start) = find_from("<", pos) {
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            pos = find_from("-->", start).map_or(html.len(), |end| end + 3);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos = find_from(">", start).map_or(html.len(), |end| end + 1);
+            continue;
+        }
+
+        let closing = rest.starts_with("</");
+        let name_start = start + if closing { 2 } else { 1 };
+        let name_end = html[name_start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':'))
+            .map_or(html.len(), |idx| idx + name_start);
+        if name_end == name_start {
+            // A stray `<` in text.
+            pos = start + 1;
+            continue;
+        }
+        let tag = html[name_start..name_end].to_ascii_lowercase();
+
+        // Finds the end of the tag, skipping the quoted attribute values.
+        let mut end = name_end;
+        let mut quote = None;
+        while end < bytes.len() {
+            match (quote, bytes[end]) {
+                (Some(q), c) if c == q => quote = None,
+                (None, b'"' | b'\'') => quote = Some(bytes[end]),
+                (None, b'>') => break,
+                _ => {}
+            }
+            end += 1;
+        }
+        let inner_start = (end + 1).min(html.len());
+        pos = inner_start;
+
+        if closing {
+            // Closes the matching element and its unclosed children.
+            if let Some(depth) = stack.iter().rposition(|elem| elem.tag == tag) {
+                while stack.len() > depth {
+                    let mut elem = stack.pop().unwrap();
+                    elem.inner_end = start;
+                    // Only the matching element owns the closing tag.
+                    elem.end = if stack.len() == depth {
+                        inner_start
+                    } else {
+                        start
+                    };
+                    push(&mut stack, &mut roots, elem);
+                }
+            }
+            continue;
+        }
+
+        let self_closing = html[..end].ends_with('/');
+        let attrs = parse_attrs(&html[name_end..end.min(html.len())]);
+        let mut elem = Element {
+            tag,
+            attrs,
+            start,
+            inner_start,
+            inner_end: inner_start,
+            end: inner_start,
+            children: vec![],
+        };
+
+        if self_closing || VOID_ELEMENTS.contains(&elem.tag.as_str()) {
+            push(&mut stack, &mut roots, elem);
+        } else if RAW_TEXT_ELEMENTS.contains(&elem.tag.as_str()) {
+            let close = format!("</{}", elem.tag);
+            let inner_end = html[inner_start..]
+                .to_ascii_lowercase()
+                .find(&close)
+                .map_or(html.len(), |idx| idx + inner_start);
+            elem.inner_end = inner_end;
+            pos = find_from(">", inner_end).map_or(html.len(), |end| end + 1);
+            elem.end = pos;
+            push(&mut stack, &mut roots, elem);
+        } else {
+            stack.push(elem);
+        }
+    }
+
+    while let Some(mut elem) = stack.pop() {
+        elem.inner_end = html.len();
+        elem.end = html.len();
+        push(&mut stack, &mut roots, elem);
+    }
+
+    roots
+}
+
+/// Parses the attributes in a start tag, e.g. ` id="a" hidden`.
+fn parse_attrs(text: &str) -> Vec<(String, String)> {
+    let text = text.trim_end_matches('/');
+    let mut attrs = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, next) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let after = &after[1..];
+                    let end = after.find(q).unwrap_or(after.len());
+                    (&after[..end], after.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_char_refs(raw);
+            rest = next;
+        } else if name.is_empty() {
+            // A stray `/` or `=`.
+            rest = &rest[1..];
+        }
+
+        if !name.is_empty() {
+            attrs.push((name, value));
+        }
+        rest = rest.trim_start();
+    }
+
+    attrs
+}
+
+/// Decodes the character references that are escaped by the html exporter.
+fn decode_char_refs(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_owned();
+    }
+
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(elems: &[Element]) -> Vec<&str> {
+        elems.iter().map(|elem| elem.tag.as_str()).collect()
+    }
+
+    #[test]
+    fn elements_are_located_by_offsets() {
+        let html = r#"<div id="a"><p>text<br></p></div><img src="x.png"/>"#;
+        let elems = parse_elements(html);
+        assert_eq!(tags(&elems), ["div", "img"]);
+
+        let div = &elems[0];
+        assert_eq!(
+            &html[div.start..div.end],
+            r#"<div id="a"><p>text<br></p></div>"#
+        );
+        assert_eq!(&html[div.inner_start..div.inner_end], "<p>text<br></p>");
+        assert_eq!(tags(&div.children), ["p"]);
+        assert_eq!(tags(&div.children[0].children), ["br"]);
+        assert_eq!(elems[1].attrs, [("src".to_owned(), "x.png".to_owned())]);
+    }
+
+    #[test]
+    fn quoted_attributes_may_contain_tag_delimiters() {
+        let html = r#"<a title="a > b" data-x='<i>'>link</a>"#;
+        let elems = parse_elements(html);
+        assert_eq!(elems.len(), 1);
+        assert!(elems[0].children.is_empty());
+        assert_eq!(&html[elems[0].inner_start..elems[0].inner_end], "link");
+    }
+
+    #[test]
+    fn raw_text_elements_are_not_parsed() {
+        let html = "<script>if (a < b) { x = '<div>'; }</script><style>p>a{}</style><p></p>";
+        let elems = parse_elements(html);
+        assert_eq!(tags(&elems), ["script", "style", "p"]);
+        assert!(elems[0].children.is_empty());
+        assert_eq!(
+            &html[elems[0].inner_start..elems[0].inner_end],
+            "if (a < b) { x = '<div>'; }"
+        );
+    }
+
+    #[test]
+    fn comments_and_doctypes_are_skipped() {
+        let html = "<!DOCTYPE html><!-- <div> --><p>a < b</p>";
+        let elems = parse_elements(html);
+        assert_eq!(tags(&elems), ["p"]);
+    }
+
+    #[test]
+    fn unclosed_elements_are_closed_by_parents() {
+        let html = "<div><p>a<span>b</div><p>c";
+        let elems = parse_elements(html);
+        assert_eq!(tags(&elems), ["div", "p"]);
+
+        let p = &elems[0].children[0];
+        assert_eq!(tags(&p.children), ["span"]);
+        // Only the matching element owns the closing tag.
+        assert_eq!(&html[p.start..p.end], "<p>a<span>b");
+        assert_eq!(&html[elems[1].start..elems[1].end], "<p>c");
+    }
+
+    #[test]
+    fn attributes_are_parsed_and_decoded() {
+        let attrs = parse_attrs(r#" id=main hidden data-x="a &quot;b&quot; &amp; c" class='x'/"#);
+        assert_eq!(
+            attrs,
+            [
+                ("id".to_owned(), "main".to_owned()),
+                ("hidden".to_owned(), String::new()),
+                ("data-x".to_owned(), r#"a "b" & c"#.to_owned()),
+                ("class".to_owned(), "x".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn character_references_are_decoded_once() {
+        assert_eq!(decode_char_refs("plain"), "plain");
+        assert_eq!(decode_char_refs("&lt;a&gt; &#39;b&#x27;"), "<a> 'b'");
+        assert_eq!(decode_char_refs("&amp;lt;"), "&lt;");
+    }
+}
@@ -0,0 +1,237 @@
+//! Splits a html document into pages by its headings.
+
+use std::collections::{HashMap, HashSet};
+
+use reflexo_typst::TypstHtmlDocument;
+use typst::{
+    ecow::{eco_format, EcoString, EcoVec},
+    introspection::{Location, Tag},
+};
+use typst_html::{attr, tag, HtmlElement, HtmlNode};
+
+use crate::error::prelude::*;
+
+/// The pages split from a html document.
+#[derive(Debug, Default)]
+pub struct SplitHtml {
+    /// The page containing the content before the first heading, if any.
+    pub preface: Option<TypstHtmlDocument>,
+    /// The pages starting with each heading, in document order.
+    pub chapters: Vec<TypstHtmlDocument>,
+}
+
+/// Splits a html document into pages at the headings of the locations, which
+/// are in document order. The pages are split in the deepest element
+/// containing all the headings, and each page keeps the rest of the document,
+/// e.g. the `<head>` and sidebars, as is.
+pub fn split_html_by_headings(doc: &TypstHtmlDocument, headings: &[Location]) -> Result<SplitHtml> {
+    if headings.is_empty() {
+        return Ok(SplitHtml::default());
+    }
+
+    // A heading starts at its start tag, which precedes its elements.
+    let mut found = HashMap::new();
+    let targets = headings.iter().collect::<HashSet<_>>();
+    find_tags(&doc.root().children, &targets, &mut vec![], &mut found);
+    let paths = headings
+        .iter()
+        .map(|loc| found.remove(loc))
+        .collect::<Option<Vec<_>>>()
+        .context("cannot split html document: heading not found in the tree")?;
+
+    // The deepest element containing all the headings.
+    let shared = paths
+        .iter()
+        .map(|path| {
+            path.iter()
+                .zip(&paths[0])
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .min()
+        .unwrap_or_default()
+        .min(paths.iter().map(Vec::len).min().unwrap_or_default() - 1);
+    let container = &paths[0][..shared];
+
+    let starts = paths.iter().map(|path| path[shared]).collect::<Vec<_>>();
+    if starts.windows(2).any(|w| w[0] >= w[1]) {
+        bail!("cannot split html document: multiple headings in a single element");
+    }
+
+    let page = |start: usize, end: usize| {
+        let mut page = doc.clone();
+        let elem = descend_mut(page.root_mut(), container);
+        elem.children = elem.children[start..end].iter().cloned().collect();
+        page
+    };
+
+    let children = &descend(doc.root(), container).children;
+    let preface = children[..starts[0]].iter().any(|node| match node {
+        HtmlNode::Tag(_) => false,
+        HtmlNode::Text(text, _) => !text.trim().is_empty(),
+        HtmlNode::Element(_) | HtmlNode::Frame(_) => true,
+    });
+    let preface = preface.then(|| page(0, starts[0]));
+
+    let ends = starts.iter().skip(1).copied().chain([children.len()]);
+    let chapters = starts
+        .iter()
+        .zip(ends)
+        .map(|(start, end)| page(*start, end))
+        .collect();
+
+    Ok(SplitHtml { preface, chapters })
+}
+
+/// Points the links to the anchors in other pages at the pages, e.g. `#intro`
+/// to `intro.html#intro`. The pages are paired with their urls.
+pub fn relink_pages(pages: &mut [(String, TypstHtmlDocument)]) {
+    let ids = pages
+        .iter()
+        .map(|(_, page)| {
+            let mut ids = HashSet::new();
+            visit_elements(&page.root().children, &mut |elem| {
+                ids.extend(elem.attrs.get(attr::id).cloned());
+            });
+            ids
+        })
+        .collect::<Vec<_>>();
+
+    // The anchors kept by every page, e.g. in sidebars, are resolved in the
+    // page itself.
+    let mut owners = HashMap::new();
+    for (idx, ids) in ids.iter().enumerate() {
+        for id in ids {
+            owners.entry(id.clone()).or_insert(idx);
+        }
+    }
+    let urls = pages.iter().map(|(url, _)| url.clone()).collect::<Vec<_>>();
+
+    for (idx, (_, page)) in pages.iter_mut().enumerate() {
+        visit_elements_mut(&mut page.root_mut().children, &mut |elem| {
+            if elem.tag != tag::a {
+                return;
+            }
+            let Some(href) = elem.attrs.get_mut(attr::href) else {
+                return;
+            };
+            let Some(id) = href.strip_prefix('#') else {
+                return;
+            };
+            if ids[idx].contains(id) {
+                return;
+            }
+            if let Some(owner) = owners.get(id) {
+                *href = eco_format!("{}#{id}", urls[*owner]);
+            }
+        });
+    }
+}
+
+/// Prefixes the `<title>` of a page, e.g. `Intro - My Book`.
+pub fn prefix_title(page: &mut TypstHtmlDocument, prefix: &str) {
+    let root = page.root_mut();
+    let Some(head) = root
+        .children
+        .make_mut()
+        .iter_mut()
+        .find_map(|node| match node {
+            HtmlNode::Element(elem) if elem.tag == tag::head => Some(elem),
+            _ => None,
+        })
+    else {
+        return;
+    };
+
+    let title = head
+        .children
+        .make_mut()
+        .iter_mut()
+        .find_map(|node| match node {
+            HtmlNode::Element(elem) if elem.tag == tag::title => Some(elem),
+            _ => None,
+        });
+    let Some(title) = title else {
+        let title = HtmlElement::new(tag::title);
+        let text = HtmlNode::text(prefix, typst::syntax::Span::detached());
+        let title = title.with_children(EcoVec::from([text]));
+        head.children.push(HtmlNode::Element(title));
+        return;
+    };
+
+    let text = title
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            HtmlNode::Text(text, _) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<String>();
+    let text = if text.trim().is_empty() {
+        EcoString::from(prefix)
+    } else {
+        eco_format!("{prefix} - {text}")
+    };
+    let span = title.span;
+    title.children = EcoVec::from([HtmlNode::text(text, span)]);
+}
+
+/// Collects the child index paths to the start tags of the located elements.
+fn find_tags(
+    nodes: &[HtmlNode],
+    targets: &HashSet<&Location>,
+    path: &mut Vec<usize>,
+    res: &mut HashMap<Location, Vec<usize>>,
+) {
+    for (idx, node) in nodes.iter().enumerate() {
+        path.push(idx);
+        match node {
+            HtmlNode::Tag(Tag::Start(elem, ..)) => {
+                if let Some(loc) = elem.location().filter(|loc| targets.contains(loc)) {
+                    res.entry(loc).or_insert_with(|| path.clone());
+                }
+            }
+            HtmlNode::Element(elem) => find_tags(&elem.children, targets, path, res),
+            _ => {}
+        }
+        path.pop();
+    }
+}
+
+fn descend<'a>(mut elem: &'a HtmlElement, path: &[usize]) -> &'a HtmlElement {
+    for idx in path {
+        let HtmlNode::Element(child) = &elem.children[*idx] else {
+            unreachable!("the path goes through elements");
+        };
+        elem = child;
+    }
+    elem
+}
+
+fn descend_mut<'a>(mut elem: &'a mut HtmlElement, path: &[usize]) -> &'a mut HtmlElement {
+    for idx in path {
+        let HtmlNode::Element(child) = &mut elem.children.make_mut()[*idx] else {
+            unreachable!("the path goes through elements");
+        };
+        elem = child;
+    }
+    elem
+}
+
+fn visit_elements(nodes: &[HtmlNode], f: &mut impl FnMut(&HtmlElement)) {
+    for node in nodes {
+        if let HtmlNode::Element(elem) = node {
+            f(elem);
+            visit_elements(&elem.children, f);
+        }
+    }
+}
+
+fn visit_elements_mut(nodes: &mut EcoVec<HtmlNode>, f: &mut impl FnMut(&mut HtmlElement)) {
+    for node in nodes.make_mut() {
+        if let HtmlNode::Element(elem) = node {
+            f(elem);
+            visit_elements_mut(&mut elem.children, f);
+        }
+    }
+}
@@ -3,3 +3,16 @@ pub mod typst;
 pub use self::typst::*;
 pub mod search;
 pub use self::search::*;
+pub mod taxonomy;
+pub use self::taxonomy::*;
+pub mod html_parse;
+pub mod html_split;
+pub use self::html_split::*;
+pub mod assets;
+pub use self::assets::*;
+pub mod embed;
+pub use self::embed::*;
+pub mod csp;
+pub use self::csp::*;
+pub mod xcommand;
+pub use self::xcommand::*;
@@ -7,7 +7,7 @@ use typst::ecow::EcoString;
 
 use crate::{
     book::meta::Search,
-    utils::{collapse_whitespace, write_file},
+    utils::{collapse_whitespace, WrittenFiles},
 };
 
 const MAX_WORD_LENGTH_TO_INDEX: usize = 80;
@@ -64,14 +64,14 @@ impl SearchRenderer {
         self.index.add_doc(&doc_ref, items);
     }
 
-    pub fn render_search_index(&mut self, dest_dir: &Path) -> Result<()> {
+    pub fn render_search_index(&mut self, dest_dir: &Path, written: &WrittenFiles) -> Result<()> {
         let index = write_to_json(&self.index, &self.config, &self.doc_urls)?;
         if index.len() > 10_000_000 {
             log::warn!("searchindex.json is very large ({} bytes)", index.len());
         }
 
-        write_file(dest_dir.join("searchindex.json"), index.as_bytes())?;
-        write_file(
+        written.write(dest_dir.join("searchindex.json"), index.as_bytes())?;
+        written.write(
             dest_dir.join("searchindex.js"),
             format!("Object.assign(window.search, {index});").as_bytes(),
         )?;
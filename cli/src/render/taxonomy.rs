@@ -0,0 +1,197 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+    sync::Mutex,
+};
+
+use reflexo_typst::path::unix_slash;
+use serde::Serialize;
+use typst::ecow::EcoString;
+
+use crate::utils::slugify;
+
+/// The file stem of the page listing all the tags, i.e. `tags/index.html`.
+pub const TAGS_INDEX: &str = "index";
+
+/// A chapter listed on a tag page.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaggedPage {
+    pub title: EcoString,
+    /// The absolute path to the chapter source, e.g. `/guide/install.typ`.
+    pub link: String,
+}
+
+/// A tag and the chapters carrying it, passed to the `tags.typ` template.
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+    pub name: String,
+    /// The file stem of the tag page, i.e. `tags/{slug}.html`.
+    pub slug: String,
+    pub pages: Vec<TaggedPage>,
+}
+
+/// The tags of a chapter indexed by [`TaxonomyCtx`].
+#[derive(Debug, Clone, PartialEq)]
+struct ChapterTags {
+    /// The index of the chapter in the book.
+    idx: usize,
+    title: EcoString,
+    tags: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct TaxonomyCtx {
+    /// The tags of the chapters indexed in a build, keyed by the chapter link.
+    items: Mutex<BTreeMap<String, ChapterTags>>,
+}
+
+impl TaxonomyCtx {
+    /// Indexes the tags of the `idx`-th chapter in the book.
+    pub fn index_tags(&self, idx: usize, path: &Path, title: EcoString, tags: &[String]) {
+        if tags.is_empty() {
+            return;
+        }
+
+        let tags = ChapterTags {
+            idx,
+            title,
+            tags: tags.to_vec(),
+        };
+        self.items.lock().unwrap().insert(chapter_link(path), tags);
+    }
+}
+
+/// The tags of all chapters in the book, which are kept across incremental
+/// builds to render the tag pages.
+#[derive(Default)]
+pub struct Taxonomy {
+    items: BTreeMap<String, ChapterTags>,
+}
+
+impl Taxonomy {
+    /// Replaces the tags by the ones indexed in a full build. Returns whether
+    /// the tags are changed.
+    pub fn replace(&mut self, ctx: TaxonomyCtx) -> bool {
+        let items = ctx.items.into_inner().unwrap();
+        let changed = items != self.items;
+        self.items = items;
+        changed
+    }
+
+    /// Updates the tags of the chapters rendered in an incremental build,
+    /// given by their paths. Returns whether the tags are changed.
+    pub fn update<'a>(
+        &mut self,
+        ctx: TaxonomyCtx,
+        rendered: impl Iterator<Item = &'a str>,
+    ) -> bool {
+        let mut items = ctx.items.into_inner().unwrap();
+
+        let mut changed = false;
+        for path in rendered {
+            let link = chapter_link(Path::new(path));
+            let tags = items.remove(&link);
+            if self.items.get(&link) != tags.as_ref() {
+                changed = true;
+                match tags {
+                    Some(tags) => self.items.insert(link, tags),
+                    None => self.items.remove(&link),
+                };
+            }
+        }
+
+        changed
+    }
+
+    /// Collects the tags in name order. The chapters of each tag are kept in
+    /// book order.
+    pub fn tags(&self) -> Vec<Tag> {
+        let mut pages = BTreeMap::<&str, Vec<_>>::new();
+        for (link, chapter) in &self.items {
+            for tag in &chapter.tags {
+                let page = TaggedPage {
+                    title: chapter.title.clone(),
+                    link: link.clone(),
+                };
+                pages.entry(tag).or_default().push((chapter.idx, page));
+            }
+        }
+
+        // The index page of the tags is at `tags/index.html`.
+        let mut slugs = HashSet::from([TAGS_INDEX.to_owned()]);
+        pages
+            .into_iter()
+            .map(|(name, mut pages)| {
+                pages.sort_by_key(|(idx, _)| *idx);
+
+                let mut slug = slugify(name);
+                if slug.is_empty() {
+                    slug = "tag".to_owned();
+                }
+                // Disambiguates tags with the same slug, e.g. `C` and `C++`.
+                if !slugs.insert(slug.clone()) {
+                    let mut idx = 2;
+                    while !slugs.insert(format!("{slug}-{idx}")) {
+                        idx += 1;
+                    }
+                    slug = format!("{slug}-{idx}");
+                }
+
+                Tag {
+                    name: name.to_owned(),
+                    slug,
+                    pages: pages.into_iter().map(|(_, page)| page).collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Gets the absolute link of a chapter, e.g. `/guide/install.typ`.
+fn chapter_link(path: &Path) -> String {
+    unix_slash(&Path::new("/").join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taxonomy(chapters: &[(&str, &[&str])]) -> Taxonomy {
+        let ctx = TaxonomyCtx::default();
+        for (idx, (path, tags)) in chapters.iter().enumerate() {
+            let tags = tags.iter().map(|tag| (*tag).to_owned()).collect::<Vec<_>>();
+            ctx.index_tags(idx, Path::new(path), (*path).into(), &tags);
+        }
+
+        let mut taxonomy = Taxonomy::default();
+        taxonomy.replace(ctx);
+        taxonomy
+    }
+
+    #[test]
+    fn slugs_of_tags_are_unique() {
+        let taxonomy = taxonomy(&[
+            ("a.typ", &["C", "C++", "Index"]),
+            ("b.typ", &["index", "!"]),
+        ]);
+        let slugs = taxonomy.tags().into_iter().map(|tag| (tag.name, tag.slug));
+        assert_eq!(
+            slugs.collect::<Vec<_>>(),
+            [
+                ("!".to_owned(), "tag".to_owned()),
+                ("C".to_owned(), "c".to_owned()),
+                ("C++".to_owned(), "c-2".to_owned()),
+                ("Index".to_owned(), "index-2".to_owned()),
+                ("index".to_owned(), "index-3".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_of_tags_are_in_book_order() {
+        let taxonomy = taxonomy(&[("b.typ", &["x"]), ("a.typ", &["x"])]);
+        let tags = taxonomy.tags();
+        let links = tags[0].pages.iter().map(|page| page.link.as_str());
+        assert_eq!(links.collect::<Vec<_>>(), ["/b.typ", "/a.typ"]);
+    }
+}
@@ -1,16 +1,17 @@
 use std::{
     borrow::Cow,
     cell::RefCell,
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     rc::Rc,
-    sync::{Arc, OnceLock},
+    sync::{Arc, Mutex, OnceLock},
 };
 
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 use reflexo_typst::{
     config::CompileOpts,
-    escape::{escape_str, AttributeEscapes},
+    escape::{escape_str, AttributeEscapes, PcDataEscapes},
     font::system::SystemFontSearcher,
     package::{registry::HttpRegistry, RegistryPathMapper},
     path::{unix_slash, PathClean},
@@ -32,43 +33,64 @@ use reflexo_vec2svg::{
     ir::{SizedRawHtmlItem, ToItemMap, VecItem},
     MultiVecDocument,
 };
-use serde::Deserialize;
 use tinymist_task::TextExport;
 use typst::{
-    diag::{SourceResult, Warned},
-    ecow::{EcoString, EcoVec},
-    foundations::{IntoValue, Output, Regex},
+    diag::{SourceDiagnostic, SourceResult, Warned},
+    ecow::{eco_format, EcoString, EcoVec},
+    foundations::{Array, IntoValue, Label, Output, Selector, Value},
+    introspection::{Introspector, MetadataElem},
+    layout::{Abs, Frame, Point, Size},
     model::Document,
+    utils::PicoStr,
     Features,
 };
+use typst_html::{HtmlAttr, HtmlAttrs, HtmlElement, HtmlNode};
+use typst_render::RenderOptions;
 
 use crate::{
     args::{CompileArgs, RenderMode},
     book::{
-        meta::{BookMetaContent, BookMetaElem},
-        outline::{outline, Outline, OutlineItem},
+        meta::{BookMetaContent, BookMetaElem, PageSummary, XCommandMeta},
+        outline::{html_headings, outline, Outline, OutlineItem},
         ChapterItem,
     },
     error::prelude::*,
-    project::ChapterArtifact,
-    render::SearchCtx,
+    project::{BuildReport, ChapterArtifact, ChapterReport, ChapterStatus},
+    render::{
+        asset_manifest,
+        embed::{EMBEDDING_TAGS, EMBED_MARKER},
+        prefix_title, relink_pages, split_html_by_headings, EmbedArgs, EmbedPolicy, SearchCtx,
+        TaxonomyCtx, XCommandRegistry,
+    },
+    tui_warn,
     utils::{
-        create_dirs, interner::SpanInternerImpl, make_absolute, make_absolute_from, write_file,
-        UnwrapOrExit,
+        create_dirs, interner::SpanInternerImpl, make_absolute, make_absolute_from, slugify,
+        UnwrapOrExit, WrittenFiles,
     },
 };
 
 const THEME_LIST: [&str; 5] = ["light", "rust", "coal", "navy", "ayu"];
+/// The size of social preview images in pixels, which is recommended by the
+/// OpenGraph consumers.
+const SOCIAL_IMAGE_SIZE: (f64, f64) = (1200., 630.);
 
 #[derive(Debug, Clone, Default)]
 pub struct CompilePageSetting {
     pub with_outline: bool,
+    /// Compiles the page from another entry file, e.g. a template shared by
+    /// generated pages. Defaults to the page itself.
+    pub entry: Option<PathBuf>,
+    /// Extra key-value pairs visible through `sys.inputs`.
+    pub inputs: Vec<(String, String)>,
 }
 
 pub struct TypstRenderer {
     pub verse: TypstSystemUniverse,
     pub snapshot: OnceLock<CompileSnapshot<SystemCompilerFeat>>,
     pub ctx: RenderContext,
+    /// The hashes of the rendered social preview images by their destination,
+    /// to skip writing the unchanged ones again.
+    social_images: Mutex<HashMap<PathBuf, u128>>,
 }
 
 impl TypstRenderer {
@@ -111,10 +133,10 @@ impl TypstRenderer {
             args.mode,
             RenderMode::StaticHtmlDynPaged | RenderMode::StaticHtml
         );
-        compiler.set_command_executor(Arc::new(ShiroaCommands(
-            args.allowed_url_source
-                .map(|s| Arc::new(Regex::new(&s).context("invalid regex").unwrap_or_exit())),
-        )));
+        let embed_policy = EmbedPolicy::new(None, args.allowed_url_source.as_deref());
+        let embed_policy = Arc::new(embed_policy.unwrap_or_exit());
+        let xcommands = Arc::new(XCommandRegistry::new(embed_policy.clone()));
+        compiler.set_command_executor(Arc::new(ShiroaCommands(xcommands.clone())));
         // compiler.set_extension("multi.sir.in".to_owned());
         compiler.set_layout_widths([750., 650., 550., 450., 350.].map(TypstAbs::pt).into());
         // let compiler =
@@ -126,6 +148,7 @@ impl TypstRenderer {
         Self {
             verse,
             snapshot: OnceLock::new(),
+            social_images: Mutex::default(),
             ctx: RenderContext {
                 output: dest_dir.clone(),
                 url_base: args.path_to_root.into(),
@@ -135,6 +158,16 @@ impl TypstRenderer {
                 dest_dir,
                 extension: "multi.sir.in".into(),
                 static_html: args.mode == RenderMode::StaticHtml,
+                include_drafts: args.drafts,
+                page_drafts: BTreeSet::new(),
+                page_summary: BTreeMap::new(),
+                outline_split_level: args.outline_split_level.map(NonZeroUsize::get),
+                outline_parts: args.outline_parts,
+                site_url: None,
+                social_image: false,
+                embed_policy,
+                xcommands,
+                written: Arc::default(),
                 diag_handler: DiagnosticHandler {
                     print_compile_status: true,
                     diagnostic_format: Default::default(),
@@ -143,6 +176,27 @@ impl TypstRenderer {
         }
     }
 
+    /// Sets the policy of the embedded html elements and the handlers of the
+    /// embedded commands declared in build-meta.
+    pub fn set_xcommands(
+        &mut self,
+        policy: EmbedPolicy,
+        declared: &BTreeMap<String, XCommandMeta>,
+    ) -> Result<()> {
+        let policy = Arc::new(policy);
+        let mut xcommands = XCommandRegistry::new(policy.clone());
+        for (kind, meta) in declared {
+            xcommands.declare(kind, meta)?;
+        }
+
+        let xcommands = Arc::new(xcommands);
+        let executor = ShiroaCommands(xcommands.clone());
+        self.ctx.compiler.set_command_executor(Arc::new(executor));
+        self.ctx.embed_policy = policy;
+        self.ctx.xcommands = xcommands;
+        Ok(())
+    }
+
     pub fn universe(&self) -> &TypstSystemUniverse {
         &self.verse
     }
@@ -165,12 +219,22 @@ impl TypstRenderer {
     }
 
     pub fn spawn_with_theme(&self, path: &Path, theme: &str) -> Result<TypstRenderTask> {
+        self.spawn_with_setting(path, theme, &CompilePageSetting::default())
+    }
+
+    pub fn spawn_with_setting(
+        &self,
+        path: &Path,
+        theme: &str,
+        settings: &CompilePageSetting,
+    ) -> Result<TypstRenderTask> {
         // self.setup_entry(path);
         if path.is_absolute() {
             panic!("entry file must be relative to the workspace");
         }
 
-        let entry = self.ctx.root_dir.join(path).clean();
+        let entry = settings.entry.as_deref().unwrap_or(path);
+        let entry = self.ctx.root_dir.join(entry).clean();
 
         let mut ctx = self.ctx.clone();
         ctx.setup_entry(path);
@@ -190,7 +254,33 @@ impl TypstRenderer {
                 dict.insert("x-target".into(), ctx.compiler.target.clone().into_value());
                 let current = unix_slash(&Path::new("/").join(path)).into_value();
                 dict.insert("x-current".into(), current);
-                for (k, v) in &self.ctx.extra_inputs {
+                dict.insert(
+                    "x-include-drafts".into(),
+                    self.ctx.include_drafts.into_value(),
+                );
+                if !self.ctx.page_drafts.is_empty() {
+                    let drafts = self.ctx.page_drafts.iter();
+                    let drafts = drafts.map(|link| link.as_str().into_value());
+                    let drafts = drafts.collect::<Array>().into_value();
+                    dict.insert("x-page-drafts".into(), drafts);
+                }
+                if !self.ctx.page_summary.is_empty() {
+                    let pages = serde_json::to_string(&self.ctx.page_summary)
+                        .context("cannot serialize page summary")?;
+                    dict.insert("x-page-summary".into(), pages.into_value());
+                }
+                if let Some(site_url) = &self.ctx.site_url {
+                    dict.insert("x-site-url".into(), site_url.clone().into_value());
+                }
+                dict.insert("x-social-image".into(), self.ctx.social_image.into_value());
+                let assets = asset_manifest().iter();
+                let assets =
+                    assets.map(|(name, asset)| ((*name).into(), asset.as_str().into_value()));
+                dict.insert(
+                    "x-asset-manifest".into(),
+                    assets.collect::<TypstDict>().into_value(),
+                );
+                for (k, v) in self.ctx.extra_inputs.iter().chain(&settings.inputs) {
                     dict.insert(k.as_str().into(), v.clone().into_value());
                 }
                 Arc::new(LazyHash::new(dict))
@@ -231,9 +321,19 @@ impl TypstRenderer {
         Ok((task, res.ok_or_else(|| error_once!("compile book.typ"))?))
     }
 
-    pub fn compile_pages_by_outline(&self, path: &Path) -> Result<Vec<BookMetaElem>> {
+    pub fn compile_pages_by_outline(&self, path: &Path) -> Result<OutlineChapters> {
+        if self.ctx.static_html {
+            return self.compile_static_pages_by_outline(path);
+        }
+
         // compile entry file as a single webpage
-        self.compile_page_with(path, CompilePageSetting { with_outline: true })?;
+        self.compile_page_with(
+            path,
+            CompilePageSetting {
+                with_outline: true,
+                ..Default::default()
+            },
+        )?;
 
         let res = THEME_LIST
             .into_par_iter()
@@ -243,8 +343,151 @@ impl TypstRenderer {
             })
             .collect::<Result<Vec<_>>>()?;
 
-        let res = res.into_iter().next();
-        res.ok_or_else(|| error_once!("compile pages by outline"))
+        let summary = res.into_iter().next();
+        let summary = summary.ok_or_else(|| error_once!("compile pages by outline"))?;
+        Ok(OutlineChapters {
+            summary,
+            ..Default::default()
+        })
+    }
+
+    /// Splits the html document of the entry file into chapters by its
+    /// headings, which are located by the introspector.
+    fn compile_static_pages_by_outline(&self, path: &Path) -> Result<OutlineChapters> {
+        let (task, doc) = self.compile_page(path)?;
+        let split_level = self.ctx.outline_split_level;
+        let html_doc = TypstDocument::Html(doc.clone());
+        let headings = html_headings(task.world(), &html_doc, split_level);
+        let locations = headings.iter().map(|h| h.location).collect::<Vec<_>>();
+        let split = split_html_by_headings(&doc, &locations)?;
+
+        let mut chapters = OutlineChapters::default();
+        let mut pages = vec![];
+        if let Some(preface) = split.preface {
+            chapters.summary.push(BookMetaElem::Chapter {
+                title: BookMetaContent::PlainText {
+                    content: "Preface".into(),
+                },
+                link: Some("pre.typ".to_owned()),
+                sub: vec![],
+                section: None,
+                draft: false,
+            });
+            chapters.summary.push(BookMetaElem::Separator {});
+            pages.push(("pre".to_owned(), "Preface".to_owned(), preface));
+        }
+
+        let mut stems = StemAllocator::default();
+        for heading in &headings {
+            stems.reserve(&chapter_stem(&heading.item));
+        }
+
+        // The chapters are nested by the levels of their headings.
+        let mut stack: Vec<(usize, BookMetaElem)> = vec![];
+        let mut numbering: Vec<usize> = vec![];
+        let pop = |stack: &mut Vec<(usize, BookMetaElem)>, summary: &mut Vec<BookMetaElem>| {
+            let (_, item) = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some((_, BookMetaElem::Chapter { sub, .. })) => sub.push(item),
+                _ => summary.push(item),
+            }
+        };
+        for (heading, page) in headings.iter().zip(split.chapters) {
+            let stem = stems.allocate(&chapter_stem(&heading.item));
+            let link = format!("{stem}.typ");
+            let title = heading.item.title.clone();
+            pages.push((stem, title.clone(), page));
+
+            let title = BookMetaContent::PlainText { content: title };
+            // The top-level headings become part titles, and their content
+            // before the first sub-heading is kept as an untitled chapter.
+            if self.ctx.outline_parts && heading.level == 1 {
+                while !stack.is_empty() {
+                    pop(&mut stack, &mut chapters.summary);
+                }
+                chapters.summary.push(BookMetaElem::Part {
+                    title: title.clone(),
+                    level: 1,
+                });
+                chapters.summary.push(BookMetaElem::Chapter {
+                    title,
+                    link: Some(link),
+                    sub: vec![],
+                    section: None,
+                    draft: false,
+                });
+                continue;
+            }
+
+            while stack
+                .last()
+                .is_some_and(|(level, _)| *level >= heading.level)
+            {
+                pop(&mut stack, &mut chapters.summary);
+            }
+            let depth = stack.len();
+            numbering.truncate(depth + 1);
+            numbering.resize(depth + 1, 0);
+            numbering[depth] += 1;
+            // The numbering of the document is kept if the heading is numbered.
+            let section = heading.item.numbering.clone().unwrap_or_else(|| {
+                let numbering = numbering.iter().map(usize::to_string);
+                numbering.collect::<Vec<_>>().join(".")
+            });
+
+            let item = BookMetaElem::Chapter {
+                title,
+                link: Some(link),
+                sub: vec![],
+                section: Some(section),
+                draft: false,
+            };
+            stack.push((heading.level, item));
+        }
+        while !stack.is_empty() {
+            pop(&mut stack, &mut chapters.summary);
+        }
+
+        let links = pages.iter().map(|(stem, ..)| format!("{stem}.typ"));
+        let links = links.collect::<Vec<_>>();
+        let mut pages = pages
+            .into_iter()
+            .map(|(stem, title, mut page)| {
+                prefix_title(&mut page, &title);
+                (format!("{stem}.html"), page)
+            })
+            .collect::<Vec<_>>();
+        relink_pages(&mut pages);
+
+        for (link, (_, page)) in links.into_iter().zip(pages) {
+            let page = Arc::new(page);
+            let res = task
+                .report(static_html(&page))
+                .context("failed to render static html")?;
+            let html = task
+                .report(res.html())
+                .context("failed to render static html")?;
+            chapters.static_pages.insert(link, html.to_owned());
+        }
+
+        Ok(chapters)
+    }
+
+    /// Compiles the html wrapper of a chapter split from the `entry` file by
+    /// outline. The content is loaded from the split paged modules, so the
+    /// paged modules are not compiled again.
+    pub fn compile_outline_wrapper(
+        &self,
+        path: &Path,
+        entry: &Path,
+    ) -> Result<(TypstRenderTask, Arc<TypstHtmlDocument>)> {
+        let settings = CompilePageSetting {
+            entry: Some(entry.to_owned()),
+            ..Default::default()
+        };
+        let mut task = self.spawn_with_setting(path, "", &settings)?;
+        let doc = task.compile_html_page_with()?;
+        Ok((task, doc))
     }
 
     pub fn compile_page(&self, path: &Path) -> Result<(TypstRenderTask, Arc<TypstHtmlDocument>)> {
@@ -256,11 +499,7 @@ impl TypstRenderer {
         path: &Path,
         settings: CompilePageSetting,
     ) -> Result<(TypstRenderTask, Arc<TypstHtmlDocument>)> {
-        if self.ctx.static_html && settings.with_outline {
-            return Err(error_once!("outline is not supported in static paged mode"));
-        }
-
-        let mut task = self.spawn(path)?;
+        let mut task = self.spawn_with_setting(path, "", &settings)?;
         let doc = task.compile_html_page_with()?;
 
         // todo: review me.
@@ -268,7 +507,7 @@ impl TypstRenderer {
             let res = THEME_LIST
                 .into_par_iter()
                 .map(|theme| {
-                    let mut task = self.spawn_with_theme(path, theme)?;
+                    let mut task = self.spawn_with_setting(path, theme, &settings)?;
                     task.compile_paged_page_with(settings.clone())
                 })
                 .collect::<Result<()>>();
@@ -278,6 +517,70 @@ impl TypstRenderer {
         Ok((task, doc))
     }
 
+    /// Renders the first page of a chapter as its social preview image, which
+    /// is placed next to the page with the `.png` extension. The image can be
+    /// rendered from another entry file by the settings, e.g. a card template.
+    ///
+    /// The page is scaled to the width of the card and cropped or padded to
+    /// its height. The image is not written again if the card is unchanged.
+    pub fn render_social_image(&self, path: &Path, settings: &CompilePageSetting) -> Result<()> {
+        let task = self.spawn_with_setting(path, THEME_LIST[0], settings)?;
+        let doc = task.pure_compile::<TypstPagedDocument>()?;
+        let page = doc.pages().first().context("no page to render")?;
+
+        let (width, height) = SOCIAL_IMAGE_SIZE;
+        let pixel_per_pt = width / page.frame.width().to_pt();
+        if !pixel_per_pt.is_finite() {
+            bail!("cannot render social image for an empty page");
+        }
+        let mut frame = Frame::hard(Size::new(
+            page.frame.width(),
+            Abs::pt(height / pixel_per_pt),
+        ));
+        frame.push_frame(Point::zero(), page.frame.clone());
+        let mut card = page.clone();
+        card.frame = frame;
+
+        let dest = self.ctx.dest_dir.join(path).with_extension("png");
+        let hash = typst::utils::hash128(&card);
+        let rendered = self.social_images.lock().unwrap().get(&dest).copied();
+        if rendered == Some(hash) && dest.exists() {
+            return Ok(());
+        }
+
+        let options = RenderOptions {
+            pixel_per_pt: pixel_per_pt.into(),
+            ..RenderOptions::default()
+        };
+        let png = typst_render::render(&card, &options)
+            .encode_png()
+            .map_err(map_string_err("encode social image"))?;
+
+        if let Some(parent) = dest.parent() {
+            create_dirs(parent)?;
+        }
+        self.ctx.written.write(&dest, png)?;
+        self.social_images.lock().unwrap().insert(dest, hash);
+        Ok(())
+    }
+
+    /// Removes the files written by compiling a chapter, which turns out to
+    /// be a draft.
+    fn discard_page(&self, path: &Path) -> Result<()> {
+        let mut ctx = self.ctx.clone();
+        ctx.setup_entry(path);
+
+        let mut outputs = vec![self.ctx.dest_dir.join(path).with_extension("png")];
+        for theme in std::iter::once("").chain(THEME_LIST) {
+            ctx.set_theme_target(theme);
+            outputs.push(ctx.module_dest_path());
+        }
+        for output in outputs {
+            self.ctx.written.remove(&output)?;
+        }
+        Ok(())
+    }
+
     pub fn generate_desc(doc: &TypstDocument) -> Result<String> {
         TextExport::run_on_doc(doc).context("export text for html description")
     }
@@ -288,48 +591,130 @@ impl TypstRenderer {
         chapters: &[ChapterItem],
         filter: &BTreeMap<ImmutStr, usize>,
         compiler: impl Fn(&str) -> Result<ChapterArtifact> + Send + Sync,
-    ) -> Result<()> {
-        chapters
+    ) -> Result<BuildReport> {
+        // The chapters marked as drafts by `page-meta` are compiled again to
+        // find whether they are still drafts.
+        let page_draft = |ch: &ChapterItem| {
+            let path = ch.path.as_deref();
+            path.is_some_and(|path| self.ctx.page_drafts.contains(path))
+        };
+        let chapters = chapters
+            .iter()
+            .filter(|ch| ctx.include_drafts || !ch.draft || page_draft(ch))
+            .collect::<Vec<_>>();
+        // The index page is a copy of the first published chapter.
+        let index = chapters
+            .iter()
+            .position(|ch| ch.path.is_some() && (ctx.include_drafts || !ch.draft));
+        let chapters = chapters
             .into_par_iter()
             .enumerate()
             .map(|(idx, ch)| {
-                if let Some(raw_path) = ch.path.as_deref() {
-                    if !filter.is_empty() && !filter.contains_key(raw_path) {
-                        return Ok(());
-                    }
+                let Some(raw_path) = ch.path.as_deref() else {
+                    return Ok(None);
+                };
+                if !filter.is_empty() && !filter.contains_key(raw_path) {
+                    return Ok(None);
+                }
 
-                    let path = ctx.dest_dir.join(raw_path);
+                let path = ctx.dest_dir.join(raw_path);
 
-                    let instant = std::time::Instant::now();
-                    log::info!("rendering chapter {raw_path}");
+                let instant = std::time::Instant::now();
+                log::info!("rendering chapter {raw_path}");
 
-                    // Compiles the chapter
-                    let art: ChapterArtifact = compiler(raw_path)?;
+                // Compiles the chapter
+                let (content, status) = match compiler(raw_path) {
+                    Ok(art) if art.meta.draft && !ctx.include_drafts => {
+                        log::info!("skipped draft chapter {raw_path}");
+                        ctx.page_drafts.lock().unwrap().insert(raw_path.to_owned());
+                        self.discard_page(Path::new(raw_path))?;
+                        return Ok(None);
+                    }
+                    Ok(art) => {
+                        if let Some(summary) = PageSummary::of_page(&art.meta) {
+                            let mut page_summary = ctx.page_summary.lock().unwrap();
+                            page_summary.insert(raw_path.to_owned(), summary);
+                        }
 
-                    let search_path = Path::new(&raw_path).with_extension("html");
-                    ctx.search.index_search(
-                        &search_path,
-                        ch.title.clone(),
-                        art.description.clone(),
-                    );
+                        let title = art.meta.title.as_deref().map(EcoString::from);
+                        let title = title.unwrap_or_else(|| ch.title.clone());
 
-                    let content = art.content;
+                        let search_path = Path::new(&raw_path).with_extension("html");
+                        ctx.search.index_search(
+                            &search_path,
+                            title.clone(),
+                            art.description.clone(),
+                        );
+                        ctx.taxonomy
+                            .index_tags(idx, Path::new(raw_path), title, &art.meta.tags);
 
-                    log::info!("rendered chapter {raw_path} in {:?}", instant.elapsed());
+                        (Some(art.content), ChapterStatus::Success)
+                    }
+                    Err(err) => {
+                        let err = eco_format!("{err}");
+                        // Writes a placeholder page so that the rest of the book keeps working.
+                        let content = ctx
+                            .keep_going
+                            .then(|| error_page(&ctx, raw_path, &ch.title, &err));
+                        (content, ChapterStatus::Failed(err))
+                    }
+                };
 
+                log::info!("rendered chapter {raw_path} in {:?}", instant.elapsed());
+
+                if let Some(content) = content {
                     create_dirs(path.parent().unwrap())?;
-                    write_file(path.with_extension("html"), &content)?;
-                    if idx == 0 {
-                        write_file(ctx.dest_dir.join("index.html"), content)?;
+                    self.ctx
+                        .written
+                        .write(path.with_extension("html"), &content)?;
+                    if Some(idx) == index {
+                        self.ctx
+                            .written
+                            .write(ctx.dest_dir.join("index.html"), content)?;
                     }
                 }
 
-                Ok(())
+                Ok(Some(ChapterReport {
+                    path: raw_path.into(),
+                    status,
+                    elapsed: instant.elapsed(),
+                }))
             })
-            .collect::<Result<()>>()
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(BuildReport {
+            chapters: chapters.into_iter().flatten().collect(),
+            ..Default::default()
+        })
     }
 }
 
+/// Renders a placeholder page for a chapter that failed to compile.
+fn error_page(ctx: &HtmlRenderContext, path: &str, title: &str, err: &str) -> String {
+    let title = escape_str::<PcDataEscapes>(title);
+    let path = escape_str::<PcDataEscapes>(path);
+    let err = escape_str::<PcDataEscapes>(err);
+    let url_base = escape_str::<AttributeEscapes>(ctx.url_base);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <meta name="robots" content="noindex">
+  <title>{title}</title>
+</head>
+<body>
+  <h1>{title}</h1>
+  <p>Failed to compile <code>{path}</code>.</p>
+  <pre>{err}</pre>
+  <p><a href="{url_base}">Back to the book</a></p>
+</body>
+</html>
+"#
+    )
+}
+
 #[derive(Clone)]
 pub struct RenderContext {
     pub extension: EcoString,
@@ -340,6 +725,27 @@ pub struct RenderContext {
     pub root_dir: PathBuf,
     pub dest_dir: PathBuf,
     static_html: bool,
+    pub include_drafts: bool,
+    /// The chapters marked as drafts by `page-meta`, which are hidden from the
+    /// summary unless the drafts are included.
+    pub page_drafts: BTreeSet<String>,
+    /// The titles and weights of the chapters set by `page-meta`, keyed by
+    /// the chapter links, which override the summary.
+    pub page_summary: BTreeMap<String, PageSummary>,
+    /// The deepest heading level that starts a new chapter in outline mode.
+    pub outline_split_level: Option<usize>,
+    /// Whether the top-level headings are part titles in outline mode.
+    pub outline_parts: bool,
+    /// The url of the deployed site, set by `site-url` in `build-meta`.
+    pub site_url: Option<EcoString>,
+    /// Whether to render the social preview image of each chapter.
+    pub social_image: bool,
+    /// The policy of the html elements embedded by `shiroa.media`.
+    pub embed_policy: Arc<EmbedPolicy>,
+    /// The handlers of the commands embedded by `xcommand`.
+    pub xcommands: Arc<XCommandRegistry>,
+    /// The files written to the dest directory, which are taken by each build.
+    pub written: Arc<WrittenFiles>,
     pub diag_handler: DiagnosticHandler,
 }
 
@@ -426,7 +832,13 @@ impl TypstRenderTask {
         };
 
         let diag = diag.iter().chain(may_value.warnings.iter());
-        let diagnostics = diag.filter(no_foreign_obj_diag);
+        self.print_diagnostics(diag.filter(no_foreign_obj_diag));
+
+        self.ctx.diag_handler.status(&rep);
+        res
+    }
+
+    fn print_diagnostics<'a>(&self, diagnostics: impl Iterator<Item = &'a SourceDiagnostic>) {
         // We currently ignore export error here
         // We lock it once to avoid concurrent write
         let _ = print_diagnostics_to(
@@ -435,15 +847,15 @@ impl TypstRenderTask {
             &mut crate::tui::out().lock(),
             DiagnosticFormat::Human,
         );
-
-        self.ctx.diag_handler.status(&rep);
-        res
     }
 
     fn compile_pages_by_outline_(&mut self, theme: &'static str) -> Result<Vec<BookMetaElem>> {
+        let split_level = self.ctx.outline_split_level;
+
         // read ir from disk
         let module_output = self.ctx.module_dest_path();
-        let module_bin = std::fs::read(module_output).unwrap_or_exit();
+        let module_bin =
+            std::fs::read(module_output).map_err(error_once_map!("read outline module"))?;
 
         let doc = MultiVecDocument::from_slice(&module_bin);
         // println!("layouts: {:#?}", doc.layouts);
@@ -528,16 +940,59 @@ impl TypstRenderTask {
         #[derive(Debug)]
         struct OutlineItemRef {
             item: BookMetaElem,
+            /// The preferred file stem of the chapter.
+            stem: ChapterStem,
+            /// The span id of the heading, which is shared by all layouts.
+            span: Option<String>,
             pages: PagesRef,
             children: Vec<OutlineItemRef>,
         }
 
+        impl OutlineItemRef {
+            /// Whether the item is the heading of the chapter. The headings are
+            /// identified by span, or by title if the span is missing.
+            fn matches(&self, chapter: &OutlineChapter) -> bool {
+                match (&self.span, &chapter.span) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => self.item == chapter.item,
+                }
+            }
+
+            /// Collects the pages of the item and all its children.
+            fn all_pages(&self, pages: &mut Vec<usize>) {
+                pages.extend(self.pages.borrow().iter().cloned());
+                for child in &self.children {
+                    child.all_pages(pages);
+                }
+            }
+        }
+
         struct OutlineChapter {
             item: BookMetaElem,
+            stem: ChapterStem,
+            span: Option<String>,
             content: Option<ModuleInterner>,
             children: Vec<OutlineChapter>,
         }
 
+        fn chapter_title(item: &BookMetaElem) -> EcoString {
+            match item {
+                BookMetaElem::Chapter {
+                    title: BookMetaContent::PlainText { content },
+                    ..
+                } => content.as_str().into(),
+                _ => EcoString::new(),
+            }
+        }
+
+        /// A heading that diverges from the outline of the first layout.
+        enum Divergence {
+            /// The heading is missing in the layout.
+            Missing(EcoString),
+            /// The heading only appears in the layout.
+            Extra(EcoString),
+        }
+
         struct BuiltOutline {
             prefix: Option<ModuleInterner>,
             chapters: Vec<OutlineChapter>,
@@ -595,6 +1050,8 @@ impl TypstRenderTask {
 
                         OutlineChapter {
                             item: item.item,
+                            stem: item.stem,
+                            span: item.span,
                             content,
                             children: Self::init_items(module, pages, item.children),
                         }
@@ -602,13 +1059,15 @@ impl TypstRenderTask {
                     .collect()
             }
 
+            /// Merges the outline of another layout into the chapters, returning
+            /// the headings that diverge from the first layout.
             fn merge(
                 &mut self,
                 module: &Module,
                 builder: ItemRefBuilder,
                 pages: &[Page],
                 items: Vec<OutlineItemRef>,
-            ) {
+            ) -> Vec<Divergence> {
                 Self::intern_pages(
                     &mut self.prefix,
                     module,
@@ -616,7 +1075,9 @@ impl TypstRenderTask {
                     builder.prefix.borrow().iter().cloned(),
                 );
 
-                Self::merge_items(module, pages, &mut self.chapters, items);
+                let mut diverged = vec![];
+                Self::merge_items(module, pages, &mut self.chapters, items, &mut diverged);
+                diverged
             }
 
             fn merge_items(
@@ -624,40 +1085,88 @@ impl TypstRenderTask {
                 pages: &[Page],
                 chapters: &mut [OutlineChapter],
                 items: Vec<OutlineItemRef>,
+                diverged: &mut Vec<Divergence>,
             ) {
-                if items.len() != chapters.len() {
-                    panic!("cannot merge outline with different chapter count");
+                let aligned = items.len() == chapters.len()
+                    && items.iter().zip(chapters.iter()).all(|(i, c)| i.matches(c));
+                if aligned {
+                    for (chapter, item) in chapters.iter_mut().zip(items) {
+                        Self::intern_pages(
+                            &mut chapter.content,
+                            module,
+                            pages,
+                            item.pages.borrow().iter().cloned(),
+                        );
+
+                        let children = item.children;
+                        Self::merge_items(module, pages, &mut chapter.children, children, diverged);
+                    }
+                    return;
                 }
-                for (idx, item) in items.into_iter().enumerate() {
-                    let chapter = &mut chapters[idx];
 
-                    if chapter.item != item.item {
-                        panic!("cannot merge outline with different chapter");
+                // The headings diverge, e.g. because of `context`-dependent
+                // content, so the items are aligned to the chapters by span. The
+                // pages of an extra heading are kept in the previous chapter.
+                let mut matched = chapters.iter().map(|_| None).collect::<Vec<_>>();
+                let mut extra_pages = chapters.iter().map(|_| vec![]).collect::<Vec<_>>();
+                let mut last = 0;
+                for item in items {
+                    let found = (0..chapters.len())
+                        .find(|&idx| matched[idx].is_none() && item.matches(&chapters[idx]));
+                    match found {
+                        Some(idx) => {
+                            last = idx;
+                            matched[idx] = Some(item);
+                        }
+                        None => {
+                            diverged.push(Divergence::Extra(chapter_title(&item.item)));
+                            if let Some(extra) = extra_pages.get_mut(last) {
+                                item.all_pages(extra);
+                            }
+                        }
                     }
+                }
+
+                for ((chapter, item), extra) in chapters.iter_mut().zip(matched).zip(extra_pages) {
+                    let (mut chapter_pages, children) = match item {
+                        Some(item) => {
+                            let item_pages = item.pages.borrow().clone();
+                            (item_pages, item.children)
+                        }
+                        None => {
+                            diverged.push(Divergence::Missing(chapter_title(&chapter.item)));
+                            (vec![], vec![])
+                        }
+                    };
+                    chapter_pages.extend(extra);
+                    chapter_pages.sort_unstable();
+                    chapter_pages.dedup();
 
                     Self::intern_pages(
                         &mut chapter.content,
                         module,
                         pages,
-                        item.pages.borrow().iter().cloned(),
+                        chapter_pages.into_iter(),
                     );
-
-                    Self::merge_items(module, pages, &mut chapter.children, item.children);
+                    Self::merge_items(module, pages, &mut chapter.children, children, diverged);
                 }
             }
         }
 
         #[derive(Default)]
         struct ItemRefBuilder {
+            split_level: Option<usize>,
             prefix: PagesRef,
             first: HashMap<usize, PagesRef>,
             lasts: HashMap<usize, PagesRef>,
         }
 
         impl ItemRefBuilder {
-            fn collect_item(&mut self, item: &OutlineItem) -> OutlineItemRef {
+            fn collect_item(&mut self, item: &OutlineItem, level: usize) -> OutlineItemRef {
                 let pages = Rc::new(RefCell::new(Vec::new()));
 
+                let stem = chapter_stem(item);
+
                 if let Some(pos) = item.position.as_ref() {
                     let page_no = pos.page_no;
                     self.first
@@ -673,30 +1182,56 @@ impl TypstRenderTask {
                         },
                         link: None,
                         sub: vec![],
-                        section: None,
+                        section: item.numbering.clone(),
+                        draft: false,
                     },
+                    stem,
+                    span: item.span.clone(),
                     pages: pages.clone(),
-                    children: self.collect_items(&item.children),
+                    // The headings deeper than the split level stay in the chapter of their parent.
+                    children: if self.split_level.is_some_and(|split| level >= split) {
+                        vec![]
+                    } else {
+                        self.collect_items(&item.children, level + 1)
+                    },
                 }
             }
 
-            fn collect_items(&mut self, item: &[OutlineItem]) -> Vec<OutlineItemRef> {
+            fn collect_items(&mut self, item: &[OutlineItem], level: usize) -> Vec<OutlineItemRef> {
                 item.iter()
-                    .map(|item| self.collect_item(item))
+                    .map(|item| self.collect_item(item, level))
                     .collect::<Vec<_>>()
             }
         }
 
         let mut built_outline: Option<BuiltOutline> = None;
+        let mut parsed: Result<()> = Ok(());
 
         for l in doc.layouts.iter() {
             l.visit_pages(&mut |t| {
-                let mut builder = ItemRefBuilder::default();
+                if parsed.is_err() {
+                    return;
+                }
+
+                let mut builder = ItemRefBuilder {
+                    split_level,
+                    ..Default::default()
+                };
                 let outline = LayoutRegionNode::customs(&t.0)
                     .find(|(k, _)| k.as_ref() == "outline")
-                    .unwrap();
-                let outline = serde_json::from_slice::<Outline>(outline.1.as_ref()).unwrap();
-                let items = builder.collect_items(&outline.items);
+                    .context("no outline in the compiled module")
+                    .and_then(|outline| {
+                        serde_json::from_slice::<Outline>(outline.1.as_ref())
+                            .context("cannot parse outline")
+                    });
+                let outline = match outline {
+                    Ok(outline) => outline,
+                    Err(err) => {
+                        parsed = Err(err);
+                        return;
+                    }
+                };
+                let items = builder.collect_items(&outline.items, 1);
                 builder
                     .first
                     .entry(1)
@@ -714,19 +1249,32 @@ impl TypstRenderTask {
                 }
                 // println!("{:#?} of pages {:#?}", items, t.1);
                 if let Some(built_outline) = built_outline.as_mut() {
-                    built_outline.merge(&doc.module, builder, &t.1, items);
+                    let width = t.1.first().map_or(0., |page| page.size.x.0);
+                    for diverged in built_outline.merge(&doc.module, builder, &t.1, items) {
+                        match diverged {
+                            Divergence::Missing(title) => tui_warn!(
+                                "outline diverges at width {width}pt in theme {theme}: heading {title:?} is missing"
+                            ),
+                            Divergence::Extra(title) => tui_warn!(
+                                "outline diverges at width {width}pt in theme {theme}: unexpected heading {title:?}"
+                            ),
+                        }
+                    }
                 } else {
                     built_outline = Some(BuiltOutline::init(&doc.module, builder, &t.1, items));
                 }
             });
         }
 
-        let built_outline = built_outline.unwrap();
+        parsed?;
+        let built_outline = built_outline.context("no layout in the compiled module")?;
 
         #[derive(Default)]
         struct SeparatedChapters {
             theme: String,
             content: HashMap<String, MultiVecDocument>,
+            stems: StemAllocator,
+            parts: bool,
         }
 
         impl SeparatedChapters {
@@ -748,13 +1296,69 @@ impl TypstRenderTask {
                         link: Some("pre.typ".to_owned()),
                         sub: vec![],
                         section: None,
+                        draft: false,
                     });
 
                     inferred.push(BookMetaElem::Separator {});
                 }
 
-                let mut numbering = vec![];
-                self.finalize_items(&origin, outline.chapters, inferred, &mut numbering);
+                self.reserve_stems(&outline.chapters);
+
+                let mut numbering = vec![0];
+                if !self.parts {
+                    self.finalize_items(&origin, outline.chapters, inferred, &mut numbering);
+                    return;
+                }
+
+                // The top-level headings become part titles, and the content
+                // before their first sub-heading is kept as an untitled chapter.
+                for part in outline.chapters {
+                    let BookMetaElem::Chapter { title, .. } = part.item else {
+                        unreachable!();
+                    };
+                    inferred.push(BookMetaElem::Part {
+                        title: title.clone(),
+                        level: 1,
+                    });
+
+                    if let Some(content) = part.content {
+                        let link = self.write_chapter(&origin, &part.stem, content);
+                        inferred.push(BookMetaElem::Chapter {
+                            title,
+                            link: Some(link),
+                            sub: vec![],
+                            section: None,
+                            draft: false,
+                        });
+                    }
+
+                    self.finalize_items(&origin, part.children, inferred, &mut numbering);
+                }
+            }
+
+            /// Reserves the stems pinned by the labels of the written chapters.
+            fn reserve_stems(&mut self, chapters: &[OutlineChapter]) {
+                for chapter in chapters {
+                    if chapter.content.is_some() {
+                        self.stems.reserve(&chapter.stem);
+                    }
+                    self.reserve_stems(&chapter.children);
+                }
+            }
+
+            /// Writes the content of a chapter, returning the link to it.
+            fn write_chapter(
+                &mut self,
+                origin: &MultiVecDocument,
+                stem: &ChapterStem,
+                content: ModuleInterner,
+            ) -> String {
+                let link_path = self.stems.allocate(stem);
+                self.content.insert(
+                    format!("{link_path}.{theme}.multi.sir.in", theme = self.theme),
+                    content.finalize(origin),
+                );
+                format!("{link_path}.typ")
             }
 
             fn finalize_items(
@@ -764,49 +1368,48 @@ impl TypstRenderTask {
                 inferred: &mut Vec<BookMetaElem>,
                 numbering: &mut Vec<usize>,
             ) {
-                numbering.push(0);
                 for OutlineChapter {
                     mut item,
+                    stem,
                     content,
                     children,
+                    ..
                 } in items
                 {
                     let BookMetaElem::Chapter {
-                        title: _,
-                        link,
-                        sub,
-                        section,
+                        link, sub, section, ..
                     } = &mut item
                     else {
                         unreachable!();
                     };
 
-                    if let Some(prefix) = content {
-                        let link_path = format!("{}", self.content.len());
-                        self.content.insert(
-                            format!("{link_path}.{theme}.multi.sir.in", theme = self.theme),
-                            prefix.finalize(origin),
-                        );
-                        *link = Some(format!("{link_path}.typ"));
+                    if let Some(content) = content {
+                        *link = Some(self.write_chapter(origin, &stem, content));
                     }
 
                     *numbering.last_mut().unwrap() += 1;
+                    numbering.push(0);
                     self.finalize_items(origin, children, sub, numbering);
-                    *section = Some(
-                        numbering
-                            .iter()
-                            .map(|s| s.to_string())
-                            .collect::<Vec<_>>()
-                            .join("."),
-                    );
+                    numbering.pop();
+                    // The numbering of the document is kept if the heading is
+                    // numbered.
+                    if section.is_none() {
+                        *section = Some(
+                            numbering
+                                .iter()
+                                .map(|s| s.to_string())
+                                .collect::<Vec<_>>()
+                                .join("."),
+                        );
+                    }
                     inferred.push(item);
                 }
-                numbering.pop();
             }
         }
 
         let mut separated_chapters = SeparatedChapters {
             theme: theme.to_owned(),
+            parts: self.ctx.outline_parts,
             ..Default::default()
         };
         let mut inferred = Vec::new();
@@ -816,7 +1419,7 @@ impl TypstRenderTask {
         for chp in separated_chapters.content {
             let mut path = self.ctx.dest_dir.clone();
             path.push(chp.0);
-            std::fs::write(path, chp.1.to_bytes()).unwrap_or_exit();
+            self.ctx.written.write(path, chp.1.to_bytes())?;
         }
 
         Ok(inferred)
@@ -832,20 +1435,35 @@ impl TypstRenderTask {
     }
 
     pub fn compile_html_page_with(&mut self) -> Result<Arc<TypstHtmlDocument>> {
-        let doc = self.pure_compile::<TypstHtmlDocument>()?;
+        let mut doc = self.pure_compile::<TypstHtmlDocument>()?;
+        self.sanitize_embeds(&mut doc);
         let res = self
             .report(static_html(&doc))
             .ok_or_else(|| error_once!("failed to render html page"))?;
         let body = self.report(res.body()).expect("failed to render body");
 
         let dest = self.ctx.module_dest_path();
-        std::fs::write(&dest, body).unwrap_or_exit();
+        self.ctx.written.write(&dest, body).unwrap_or_exit();
 
         Ok(doc)
     }
 
+    /// Drops the embedded html elements violating the embed policy from the
+    /// page, which are reported as warnings.
+    fn sanitize_embeds(&self, doc: &mut Arc<TypstHtmlDocument>) {
+        let warnings = sanitize_embeds(doc, &self.ctx.embed_policy);
+        if !warnings.is_empty() {
+            self.print_diagnostics(warnings.iter());
+        }
+    }
+
     pub fn compile_paged_page_with(&mut self, settings: CompilePageSetting) -> Result<()> {
         // let path = path.clone().to_owned();
+        // The interner is shared by all layouts, so that a heading has the same
+        // span id at every width.
+        let spans = Mutex::new(SpanInternerImpl::default());
+        // The numbering of headings is displayed by the counters of the world.
+        let world = self.world().clone();
         self.ctx
             .compiler
             .set_post_process_layout(move |_m, doc, layout| {
@@ -883,9 +1501,9 @@ impl TypstRenderTask {
                 let mut custom = vec![sema_label_meta];
 
                 if settings.with_outline {
-                    let mut spans = SpanInternerImpl::default();
+                    let mut spans = spans.lock().unwrap();
 
-                    let outline = outline(&mut spans, &doc);
+                    let outline = outline(&mut spans, &world, &doc);
                     let outline = serde_json::to_vec(&outline).unwrap_or_exit();
                     let outline_meta = ("outline".into(), outline.into());
                     custom.push(outline_meta);
@@ -900,14 +1518,14 @@ impl TypstRenderTask {
         if let Some(doc) = res {
             let content = doc.to_bytes();
             let dest = self.ctx.module_dest_path();
-            std::fs::write(&dest, content).unwrap_or_exit();
+            self.ctx.written.write(&dest, content).unwrap_or_exit();
         }
 
         Ok(())
     }
 }
 
-struct ShiroaCommands(Option<Arc<Regex>>);
+struct ShiroaCommands(Arc<XCommandRegistry>);
 
 impl reflexo_typst::vector::pass::CommandExecutor for ShiroaCommands {
     fn execute(
@@ -925,114 +1543,104 @@ impl reflexo_typst::vector::pass::CommandExecutor for ShiroaCommands {
                 text.find("embedded-content -->").map(|end| &text[0..end])
             })?
             .trim();
-        let (cmd, payload) = content.split_once(',')?;
-
-        match cmd {
-            "html" => {
-                let args = serde_json::from_str::<HtmlCommandArgs>(payload).ok()?;
-
-                // todo: disallow iframe?
-                let allowed_tags = TAGS_META.get_or_init(|| {
-                    HashMap::from_iter([
-                        (
-                            "iframe",
-                            (
-                                "",
-                                HashSet::from_iter([
-                                    "id",
-                                    "class",
-                                    "src",
-                                    "allowfullscreen",
-                                    "scrolling",
-                                    "framespacing",
-                                    "frameborder",
-                                    "border",
-                                    "width",
-                                    "height",
-                                ]),
-                            ),
-                        ),
-                        ("div", ("", HashSet::from_iter(["id", "class"]))),
-                        (
-                            "audio",
-                            (
-                                "audio.",
-                                HashSet::from_iter(["id", "class", "src", "controls"]),
-                            ),
-                        ),
-                        (
-                            "video",
-                            (
-                                "video.",
-                                HashSet::from_iter(["id", "class", "src", "controls"]),
-                            ),
-                        ),
-                    ])
-                });
 
-                let tag = args.tag;
-                let Some((hint, allowed_attrs)) = allowed_tags.get(tag.as_str()) else {
-                    log::warn!("disallowed tag: {tag}");
-                    return None;
-                };
-                let allow_attr = |k: &str| k.starts_with("data-") || allowed_attrs.contains(k);
+        let html = self.0.execute(content)?;
+        Some(VecItem::SizedRawHtml(SizedRawHtmlItem {
+            html: html.into(),
+            size: size.unwrap_or_default().into_typst(),
+        }))
+    }
+}
 
-                let attributes = args.attributes;
+/// The chapters split from a document by its outline.
+#[derive(Debug, Default)]
+pub struct OutlineChapters {
+    /// The summary inferred from the outline.
+    pub summary: Vec<BookMetaElem>,
+    /// The html of each chapter keyed by the chapter path, if the chapters are
+    /// rendered statically.
+    pub static_pages: HashMap<String, String>,
+}
 
-                let mut attrs = String::new();
-                for (k, v) in attributes {
-                    if k.contains(|c: char| !c.is_ascii_alphanumeric()) || !allow_attr(&k) {
-                        log::warn!("disallowed attribute: {k} on tag {tag}");
-                        return None;
-                    }
+/// The preferred file stem of a chapter split by outline.
+#[derive(Debug, Clone, PartialEq)]
+struct ChapterStem {
+    stem: String,
+    /// Whether the stem is pinned by a heading label.
+    pinned: bool,
+}
 
-                    if k == "src" {
-                        let Some(v) = url::Url::parse(&v).ok() else {
-                            log::warn!("invalid source url: {v} on tag {tag}");
-                            return None;
-                        };
+/// Gets the file stem of a chapter split by outline. A heading label pins the
+/// file name of the chapter.
+fn chapter_stem(item: &OutlineItem) -> ChapterStem {
+    let label = item.label.as_deref().map(slugify);
+    match label.filter(|stem| !stem.is_empty()) {
+        Some(stem) => ChapterStem { stem, pinned: true },
+        None => ChapterStem {
+            stem: slugify(&item.title),
+            pinned: false,
+        },
+    }
+}
 
-                        if v.scheme() != "http" && v.scheme() != "https" {
-                            log::warn!("invalid source url scheme: {v} on tag {tag}");
-                            return None;
-                        }
+/// Allocates the file stems of the chapters split by outline, disambiguating
+/// the chapters with the same title by a numeric suffix. The pinned stems are
+/// reserved first, so that they are never taken by the slug of a title.
+struct StemAllocator {
+    taken: HashSet<String>,
+    reserved: HashSet<String>,
+}
 
-                        let allowed = self
-                            .0
-                            .as_ref()
-                            .map(|re| v.host_str().is_some_and(|host| re.is_match(host)))
-                            .unwrap_or(false);
-                        if !allowed {
-                            log::warn!("disallowed source url: {v} on tag {tag}");
-                            return None;
-                        }
-                    }
+impl Default for StemAllocator {
+    fn default() -> Self {
+        // `pre` is the preface and `index` is the copy of the first chapter.
+        Self {
+            taken: HashSet::from_iter(["pre".to_owned(), "index".to_owned()]),
+            reserved: HashSet::new(),
+        }
+    }
+}
 
-                    attrs.push_str(&format!(" {k}=\"{}\"", escape_str::<AttributeEscapes>(&v)));
-                }
+impl StemAllocator {
+    /// Reserves the stem if it is pinned by a heading label.
+    fn reserve(&mut self, stem: &ChapterStem) {
+        if stem.pinned && !self.taken.contains(&stem.stem) {
+            self.reserved.insert(stem.stem.clone());
+        }
+    }
 
-                let html = format!("<{tag}{attrs}>{hint}</{tag}>");
-                return Some(VecItem::SizedRawHtml(SizedRawHtmlItem {
-                    html: html.into(),
-                    size: size.unwrap_or_default().into_typst(),
-                }));
-            }
-            "ping" => {}
-            _ => {}
+    fn allocate(&mut self, stem: &ChapterStem) -> String {
+        if stem.pinned && self.reserved.remove(&stem.stem) {
+            self.taken.insert(stem.stem.clone());
+            return stem.stem.clone();
         }
 
-        None
+        let stem = if stem.stem.is_empty() {
+            "chapter"
+        } else {
+            &stem.stem
+        };
+        if self.is_free(stem) {
+            self.taken.insert(stem.to_owned());
+            return stem.to_owned();
+        }
+
+        let mut idx = 2;
+        loop {
+            let candidate = format!("{stem}-{idx}");
+            if self.is_free(&candidate) {
+                self.taken.insert(candidate.clone());
+                return candidate;
+            }
+            idx += 1;
+        }
     }
-}
 
-#[derive(Debug, Clone, Default, Deserialize)]
-struct HtmlCommandArgs {
-    tag: String,
-    attributes: HashMap<String, String>,
+    fn is_free(&self, stem: &str) -> bool {
+        !self.taken.contains(stem) && !self.reserved.contains(stem)
+    }
 }
 
-static TAGS_META: OnceLock<HashMap<&str, (&str, HashSet<&str>)>> = OnceLock::new();
-
 fn no_foreign_obj_diag(diag: &&typst::diag::SourceDiagnostic) -> bool {
     if diag.severity == typst::diag::Severity::Error {
         return true;
@@ -1058,5 +1666,395 @@ fn print_diag_or_error<T>(world: &impl SourceWorld, result: Result<T>) -> Result
 
 pub struct HtmlRenderContext<'a> {
     pub search: &'a SearchCtx<'a>,
+    pub taxonomy: &'a TaxonomyCtx,
+    /// Collects the chapters marked as drafts by `page-meta`, which are not
+    /// rendered.
+    pub page_drafts: &'a Mutex<BTreeSet<String>>,
+    /// Collects the titles and weights of the chapters set by `page-meta`.
+    pub page_summary: &'a Mutex<BTreeMap<String, PageSummary>>,
     pub dest_dir: &'a Path,
+    pub url_base: &'a str,
+    /// Writes an error page for the failed chapters instead of skipping them.
+    pub keep_going: bool,
+    /// Renders the draft chapters.
+    pub include_drafts: bool,
+}
+
+/// Checks the embedded elements against the embed policy, dropping the
+/// violating ones from the html tree and setting the forced attributes on the
+/// others. The violations of the elements embedded by `shiroa.media` are
+/// reported at the calls in the chapters, which are carried by the arguments
+/// of the calls, rather than in the package. The elements created by
+/// `html.elem` are reported at themselves.
+fn sanitize_embeds(
+    doc: &mut Arc<TypstHtmlDocument>,
+    policy: &EmbedPolicy,
+) -> EcoVec<SourceDiagnostic> {
+    let mut warnings = EcoVec::new();
+    if let Some(label) = Label::new(PicoStr::intern("shiroa-embed")) {
+        for embed in doc.introspector().query(&Selector::Label(label)) {
+            let Some(Value::Dict(value)) = embed.to_packed::<MetadataElem>().map(|m| &m.value)
+            else {
+                continue;
+            };
+            let Ok(args) =
+                serde_json::to_value(value).and_then(serde_json::from_value::<EmbedArgs>)
+            else {
+                continue;
+            };
+            let Err(violations) = policy.check(&args) else {
+                continue;
+            };
+
+            let span = match value.get("call") {
+                Ok(Value::Args(call)) => call.span,
+                _ => embed.span(),
+            };
+            for violation in violations {
+                let diag = SourceDiagnostic::warning(span, violation)
+                    .with_hint("the element is dropped, see `embed` in build-meta");
+                warnings.push(diag);
+            }
+        }
+    }
+
+    let root = &doc.root().children;
+    if let Some(children) = sanitize_html_nodes(root, Embedded::No, policy, &mut warnings) {
+        Arc::make_mut(doc).root_mut().children = children;
+    }
+    warnings
+}
+
+/// Whether the html nodes are in an embedding element.
+#[derive(Clone, Copy, PartialEq)]
+enum Embedded {
+    No,
+    /// In an element allowed by the policy, whose content is also checked.
+    Yes,
+    /// In an element embedded by `shiroa.media`, whose violations are already
+    /// reported at the call.
+    ByMedia,
+}
+
+/// Sanitizes the embedding elements in the html nodes, returning the changed
+/// nodes, if any.
+fn sanitize_html_nodes(
+    nodes: &EcoVec<HtmlNode>,
+    embedded: Embedded,
+    policy: &EmbedPolicy,
+    warnings: &mut EcoVec<SourceDiagnostic>,
+) -> Option<EcoVec<HtmlNode>> {
+    let mut changed = None::<EcoVec<HtmlNode>>;
+    for (idx, node) in nodes.iter().enumerate() {
+        let sanitized = match node {
+            HtmlNode::Element(elem) => sanitize_html_elem(elem, embedded, policy, warnings),
+            _ => Sanitized::Kept,
+        };
+        match (sanitized, &mut changed) {
+            (Sanitized::Kept, None) => {}
+            (Sanitized::Kept, Some(changed)) => changed.push(node.clone()),
+            (sanitized, changed) => {
+                let changed = changed.get_or_insert_with(|| nodes[..idx].iter().cloned().collect());
+                if let Sanitized::Replaced(elem) = sanitized {
+                    changed.push(HtmlNode::Element(elem));
+                }
+            }
+        }
+    }
+    changed
+}
+
+/// The result of sanitizing an html element.
+enum Sanitized {
+    Kept,
+    Dropped,
+    Replaced(HtmlElement),
+}
+
+/// Sanitizes an html element and its content.
+fn sanitize_html_elem(
+    elem: &HtmlElement,
+    embedded: Embedded,
+    policy: &EmbedPolicy,
+    warnings: &mut EcoVec<SourceDiagnostic>,
+) -> Sanitized {
+    let tag = elem.tag.resolve().to_string();
+    if embedded == Embedded::No && !EMBEDDING_TAGS.contains(&tag.as_str()) {
+        let mut attrs = elem.attrs.0.iter();
+        let embedded = if attrs.any(|(k, _)| k.resolve().as_str() == EMBED_MARKER) {
+            Embedded::ByMedia
+        } else {
+            Embedded::No
+        };
+        return match sanitize_html_nodes(&elem.children, embedded, policy, warnings) {
+            Some(children) => Sanitized::Replaced(elem.clone().with_children(children)),
+            None => Sanitized::Kept,
+        };
+    }
+
+    let attributes = elem.attrs.0.iter();
+    let attributes = attributes.map(|(k, v)| (k.resolve().to_string(), v.to_string()));
+    let args = EmbedArgs {
+        tag,
+        attributes: attributes.collect(),
+    };
+    let attrs = match policy.check(&args) {
+        Ok(attrs) => attrs,
+        Err(violations) => {
+            if embedded != Embedded::ByMedia {
+                for violation in violations {
+                    let diag = SourceDiagnostic::warning(elem.span, violation)
+                        .with_hint("the element is dropped, see `embed` in build-meta");
+                    warnings.push(diag);
+                }
+            }
+            return Sanitized::Dropped;
+        }
+    };
+
+    // The content, e.g. `<source>` in `<video>`, is also checked.
+    let content = match embedded {
+        Embedded::ByMedia => Embedded::ByMedia,
+        _ => Embedded::Yes,
+    };
+    let children = sanitize_html_nodes(&elem.children, content, policy, warnings);
+    let attrs = attrs
+        .into_iter()
+        .filter_map(|(k, v)| Some((HtmlAttr::intern(&k).ok()?, EcoString::from(v))))
+        .collect::<EcoVec<_>>();
+    if children.is_none() && attrs == elem.attrs.0 {
+        return Sanitized::Kept;
+    }
+
+    let mut elem = elem.clone();
+    elem.attrs = HtmlAttrs(attrs);
+    if let Some(children) = children {
+        elem.children = children;
+    }
+    Sanitized::Replaced(elem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn title(stem: &str) -> ChapterStem {
+        ChapterStem {
+            stem: stem.to_owned(),
+            pinned: false,
+        }
+    }
+
+    fn label(stem: &str) -> ChapterStem {
+        ChapterStem {
+            stem: stem.to_owned(),
+            pinned: true,
+        }
+    }
+
+    #[test]
+    fn stems_of_same_titles_are_numbered() {
+        let mut stems = StemAllocator::default();
+        assert_eq!(stems.allocate(&title("intro")), "intro");
+        assert_eq!(stems.allocate(&title("intro")), "intro-2");
+        assert_eq!(stems.allocate(&title("intro")), "intro-3");
+        assert_eq!(stems.allocate(&title("")), "chapter");
+        assert_eq!(stems.allocate(&title("")), "chapter-2");
+    }
+
+    #[test]
+    fn stems_of_generated_pages_are_taken() {
+        let mut stems = StemAllocator::default();
+        assert_eq!(stems.allocate(&title("index")), "index-2");
+        assert_eq!(stems.allocate(&label("pre")), "pre-2");
+    }
+
+    #[test]
+    fn pinned_stems_are_reserved_first() {
+        let mut stems = StemAllocator::default();
+        stems.reserve(&label("intro"));
+        stems.reserve(&label("intro-2"));
+
+        // A title before the labelled heading never takes its stem.
+        assert_eq!(stems.allocate(&title("intro")), "intro-3");
+        assert_eq!(stems.allocate(&label("intro")), "intro");
+        assert_eq!(stems.allocate(&label("intro-2")), "intro-2");
+        // The same label is pinned once.
+        assert_eq!(stems.allocate(&label("intro")), "intro-4");
+    }
+
+    /// Compiles a html document from a source in the repository root, which
+    /// is not written to the disk.
+    fn compile_html(chapter: &str, source: &str) -> (TypstRenderTask, Arc<TypstHtmlDocument>) {
+        use reflexo_typst::{world::ShadowApi, Bytes};
+
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let root = root.to_string_lossy().into_owned();
+        let mut tr = TypstRenderer::new(CompileArgs {
+            dir: root.clone(),
+            workspace: root.clone(),
+            mode: RenderMode::StaticHtml,
+            ..Default::default()
+        });
+
+        let path = Path::new(&root).join(chapter);
+        tr.verse
+            .map_shadow(&path, Bytes::from_string(source.to_owned()))
+            .unwrap();
+
+        let task = tr
+            .spawn_with_setting(Path::new(chapter), "", &Default::default())
+            .unwrap();
+        let doc = task.pure_compile::<TypstHtmlDocument>().unwrap();
+        (task, doc)
+    }
+
+    fn render_html(page: TypstHtmlDocument) -> String {
+        let res = static_html(&Arc::new(page)).unwrap();
+        res.html().unwrap().to_owned()
+    }
+
+    #[test]
+    fn html_is_split_by_headings() {
+        let source = "#set document(title: \"Book\")\n\
+            = Intro <intro>\n\
+            See #link(<usage>)[usage].\n\
+            == Details\n\
+            = Usage <usage>\n\
+            Back to #link(<intro>)[intro].\n";
+        let (task, doc) = compile_html("outline-split.typ", source);
+
+        let html_doc = TypstDocument::Html(doc.clone());
+        let headings = html_headings(task.world(), &html_doc, Some(1));
+        let locations = headings.iter().map(|h| h.location).collect::<Vec<_>>();
+        let split = split_html_by_headings(&doc, &locations).unwrap();
+        assert!(split.preface.is_none());
+        assert_eq!(split.chapters.len(), 2);
+
+        let mut pages = ["intro.html", "usage.html"]
+            .into_iter()
+            .map(str::to_owned)
+            .zip(split.chapters)
+            .collect::<Vec<_>>();
+        prefix_title(&mut pages[0].1, "Intro");
+        relink_pages(&mut pages);
+        let mut pages = pages.into_iter().map(|(_, page)| render_html(page));
+
+        let intro = pages.next().unwrap();
+        assert!(intro.contains("<title>Intro - Book</title>"));
+        assert!(intro.contains("Details"));
+        assert!(intro.contains(r#"href="usage.html#usage""#));
+        assert!(!intro.contains("Back to"));
+
+        let usage = pages.next().unwrap();
+        assert!(usage.contains("<title>Book</title>"));
+        assert!(usage.contains(r#"href="intro.html#intro""#));
+        assert!(!usage.contains("See"));
+    }
+
+    #[test]
+    fn html_is_split_by_all_headings_without_split_level() {
+        let source = "Preface\n= Intro\n== Details\n= Usage\n";
+        let (task, doc) = compile_html("outline-split-all.typ", source);
+
+        let html_doc = TypstDocument::Html(doc.clone());
+        let headings = html_headings(task.world(), &html_doc, None);
+        let levels = headings.iter().map(|h| h.level).collect::<Vec<_>>();
+        assert_eq!(levels, [1, 2, 1]);
+
+        let locations = headings.iter().map(|h| h.location).collect::<Vec<_>>();
+        let split = split_html_by_headings(&doc, &locations).unwrap();
+        assert!(render_html(split.preface.unwrap()).contains("Preface"));
+        assert_eq!(split.chapters.len(), 3);
+    }
+
+    #[test]
+    fn heading_numbering_is_displayed_by_counter() {
+        let source = "#set heading(numbering: \"1.a.\")\n\
+            = A\n\
+            == B\n\
+            #counter(heading).update(5)\n\
+            = C\n\
+            #set heading(numbering: (..nums) => \"Ch\" + str(nums.pos().first()))\n\
+            = D\n\
+            #set heading(numbering: none)\n\
+            = E\n";
+        let (task, doc) = compile_html("outline-numbering.typ", source);
+
+        let html_doc = TypstDocument::Html(doc.clone());
+        let headings = html_headings(task.world(), &html_doc, None);
+        let numbering = headings.iter().map(|h| h.item.numbering.as_deref());
+        let numbering = numbering.collect::<Vec<_>>();
+        assert_eq!(
+            numbering,
+            [Some("1"), Some("1.a"), Some("6"), Some("Ch7"), None]
+        );
+    }
+
+    #[test]
+    fn embed_violations_are_reported_at_calls() {
+        use typst::WorldExt;
+
+        let source = "#import \"/packages/shiroa/media.typ\"\n\
+            #media.iframe(attributes: (src: \"javascript:alert(1)\"))\n";
+        let (task, mut doc) = compile_html("embed-violation.typ", source);
+        let warnings = sanitize_embeds(&mut doc, &task.ctx.embed_policy);
+
+        assert_eq!(warnings.len(), 1);
+        let id = warnings[0].span.id().expect("detached span");
+        assert_eq!(id.vpath().get_with_slash(), "/embed-violation.typ");
+        let range = task.world().range(warnings[0].span).unwrap();
+        assert!(source[range].starts_with("media.iframe("));
+    }
+
+    #[test]
+    fn html_elem_violations_are_reported_at_elements() {
+        use typst::WorldExt;
+
+        let source =
+            "#html.elem(\"div\", html.elem(\"iframe\", attrs: (src: \"https://a.net\")))\n\
+            #html.elem(\"video\", attrs: (controls: \"\"))[\n\
+            #html.elem(\"embed\", attrs: (src: \"https://a.net/e\"))\n\
+            ]\n";
+        let (task, mut doc) = compile_html("embed-html-elem.typ", source);
+        let warnings = sanitize_embeds(&mut doc, &task.ctx.embed_policy);
+
+        let calls = warnings.iter().map(|warning| {
+            let range = task.world().range(warning.span).unwrap();
+            let call = &source[range];
+            call[..call.find(',').unwrap()].to_owned()
+        });
+        assert_eq!(
+            calls.collect::<Vec<_>>(),
+            ["html.elem(\"iframe\"", "html.elem(\"embed\""]
+        );
+    }
+
+    #[test]
+    fn violating_elements_are_dropped_from_html() {
+        let source = "#html.elem(\"p\")[a]\n\
+            #html.elem(\"iframe\", attrs: (src: \"https://player.bilibili.com/1\", onload: \"x()\"))\n\
+            #html.elem(\"video\", attrs: (controls: \"\"), html.elem(\"object\", attrs: (data: \"x\")))\n\
+            #html.elem(\"iframe\", attrs: (src: \"https://player.bilibili.com/2\", class: \"c\"))\n\
+            #import \"/packages/shiroa/media.typ\"\n\
+            #media.iframe(attributes: (src: \"javascript:alert(1)\"))\n";
+        let (_, mut doc) = compile_html("embed-dropped.typ", source);
+        let policy = EmbedPolicy::new(None, Some(r"^player\.bilibili\.com$")).unwrap();
+        let warnings = sanitize_embeds(&mut doc, &policy);
+        let html = render_html(Arc::unwrap_or_clone(doc));
+
+        let messages = warnings.iter().map(|warning| warning.message.as_str());
+        assert_eq!(
+            messages.collect::<Vec<_>>(),
+            [
+                "disallowed source url scheme: javascript:alert(1) on tag iframe",
+                "disallowed attribute: onload on tag iframe",
+                "disallowed tag: object",
+            ]
+        );
+        assert!(!html.contains("player.bilibili.com/1"));
+        assert!(html.contains("<video controls></video>"), "{html}");
+        assert!(html.contains(r#"<iframe class="c" src="https://player.bilibili.com/2"></iframe>"#));
+        assert!(!html.contains("javascript:"));
+    }
 }
@@ -0,0 +1,506 @@
+//! The handlers of the commands embedded by `xcommand`, e.g. `html` and
+//! `svg`. A command carries a kind and a payload, and is rendered as raw html
+//! by the handler registered for its kind.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use reflexo_typst::escape::{escape_str, PcDataEscapes};
+use serde::Deserialize;
+use typst::ecow::{eco_format, EcoString};
+
+use crate::{
+    book::meta::XCommandMeta,
+    error::prelude::*,
+    render::{
+        embed::start_tag,
+        html_parse::{parse_elements, Element},
+        EmbedArgs, EmbedPolicy,
+    },
+};
+
+/// The attribute of the placeholder of a command in html output, which is
+/// replaced by the rendered command.
+const XCOMMAND_MARKER: &str = "data-shiroa-xcommand";
+/// The attribute of the placeholder holding the payload of the command.
+const XCOMMAND_PAYLOAD: &str = "data-payload";
+
+/// The tags that can't be rendered by a declared handler.
+const RESERVED_TAGS: [&str; 12] = [
+    "script", "style", "link", "meta", "base", "iframe", "frame", "frameset", "embed", "object",
+    "portal", "template",
+];
+
+/// A handler of embedded commands.
+pub trait XCommandHandler: Send + Sync {
+    /// Renders the payload of a command as raw html, or `None` if the payload
+    /// is invalid.
+    fn render(&self, payload: &str) -> Option<String>;
+}
+
+/// The registry of the handlers, keyed by the command kind.
+#[derive(Clone)]
+pub struct XCommandRegistry {
+    handlers: BTreeMap<String, Arc<dyn XCommandHandler>>,
+}
+
+/// A command in a html page that can't be rendered.
+#[derive(Debug, Clone)]
+pub struct XCommandFailure {
+    /// The byte offset of the placeholder in the page.
+    pub offset: usize,
+    pub message: EcoString,
+}
+
+impl XCommandRegistry {
+    /// Creates a registry with the builtin handlers.
+    pub fn new(embed: Arc<EmbedPolicy>) -> Self {
+        let mut registry = Self {
+            handlers: BTreeMap::new(),
+        };
+        registry.register("html", EmbedHandler(embed));
+        registry.register("svg", SvgHandler);
+        registry.register("playground", PlaygroundHandler);
+        registry.register("details", DetailsHandler);
+        registry
+    }
+
+    pub fn register(&mut self, kind: &str, handler: impl XCommandHandler + 'static) {
+        self.handlers.insert(kind.to_owned(), Arc::new(handler));
+    }
+
+    /// Registers a handler declared in build-meta. The builtin handlers can't
+    /// be overridden.
+    pub fn declare(&mut self, kind: &str, meta: &XCommandMeta) -> Result<()> {
+        if self.handlers.contains_key(kind) {
+            bail!("cannot declare xcommand {kind}: it is a builtin command");
+        }
+
+        let tag = meta.tag.to_ascii_lowercase();
+        if !is_valid_name(&tag) || RESERVED_TAGS.contains(&tag.as_str()) {
+            bail!("cannot declare xcommand {kind}: disallowed tag {tag}");
+        }
+        let mut attrs = meta.attributes.keys();
+        if let Some(attr) = attrs.find(|k| !is_valid_name(k) || k.starts_with("on")) {
+            bail!("cannot declare xcommand {kind}: disallowed attribute {attr}");
+        }
+
+        self.register(
+            kind,
+            DeclaredHandler(XCommandMeta {
+                tag,
+                ..meta.clone()
+            }),
+        );
+        Ok(())
+    }
+
+    /// Executes a command, e.g. `svg,{"svg":"<svg>...</svg>"}`, in the paged
+    /// output.
+    pub fn execute(&self, command: &str) -> Option<String> {
+        let (kind, payload) = command.split_once(',')?;
+        self.handlers.get(kind)?.render(payload)
+    }
+
+    /// Replaces the placeholders of the commands in html output with the
+    /// rendered commands. The placeholders that can't be rendered are
+    /// removed.
+    pub fn expand_html(&self, html: &str) -> (String, Vec<XCommandFailure>) {
+        let mut edits = vec![];
+        let mut failures = vec![];
+        self.expand_elements(&parse_elements(html), &mut edits, &mut failures);
+        if edits.is_empty() {
+            return (html.to_owned(), failures);
+        }
+
+        // The placeholders don't nest, so the edits are applied from the end.
+        let mut html = html.to_owned();
+        edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+        for (range, replacement) in edits {
+            html.replace_range(range, &replacement);
+        }
+        (html, failures)
+    }
+
+    fn expand_elements(
+        &self,
+        elems: &[Element],
+        edits: &mut Vec<(std::ops::Range<usize>, String)>,
+        failures: &mut Vec<XCommandFailure>,
+    ) {
+        for elem in elems {
+            let attr = |name: &str| elem.attrs.iter().find(|(k, _)| k == name);
+            let Some((_, kind)) = attr(XCOMMAND_MARKER) else {
+                self.expand_elements(&elem.children, edits, failures);
+                continue;
+            };
+
+            let payload = attr(XCOMMAND_PAYLOAD).map_or("", |(_, v)| v.as_str());
+            let rendered = match self.handlers.get(kind.as_str()) {
+                Some(handler) => handler.render(payload).ok_or("invalid payload of xcommand"),
+                None => Err("unknown xcommand"),
+            };
+            let replacement = rendered.unwrap_or_else(|message| {
+                failures.push(XCommandFailure {
+                    offset: elem.start,
+                    message: eco_format!("{message}: {kind}"),
+                });
+                String::new()
+            });
+            edits.push((elem.start..elem.end, replacement));
+        }
+    }
+}
+
+/// Renders the html elements checked by the embed policy.
+struct EmbedHandler(Arc<EmbedPolicy>);
+
+impl XCommandHandler for EmbedHandler {
+    fn render(&self, payload: &str) -> Option<String> {
+        let args = serde_json::from_str::<EmbedArgs>(payload).ok()?;
+        // The violations are reported when compiling the html page.
+        self.0.render(&args)
+    }
+}
+
+/// Renders inline svg, keeping only the drawing elements and the attributes
+/// that can't run scripts or load external resources.
+struct SvgHandler;
+
+/// The elements kept in sanitized svg, besides the filter primitives.
+const SVG_ELEMENTS: [&str; 27] = [
+    "svg",
+    "g",
+    "defs",
+    "symbol",
+    "use",
+    "title",
+    "desc",
+    "path",
+    "rect",
+    "circle",
+    "ellipse",
+    "line",
+    "polyline",
+    "polygon",
+    "text",
+    "tspan",
+    "textpath",
+    "image",
+    "lineargradient",
+    "radialgradient",
+    "stop",
+    "clippath",
+    "mask",
+    "pattern",
+    "marker",
+    "filter",
+    "switch",
+];
+
+#[derive(Deserialize)]
+struct SvgArgs {
+    svg: String,
+}
+
+impl XCommandHandler for SvgHandler {
+    fn render(&self, payload: &str) -> Option<String> {
+        let args = serde_json::from_str::<SvgArgs>(payload).ok()?;
+        let elems = parse_elements(&args.svg);
+        let root = elems.iter().find(|elem| elem.tag == "svg")?;
+
+        let mut svg = String::new();
+        sanitize_svg(&args.svg, root, &mut svg);
+        Some(svg)
+    }
+}
+
+fn sanitize_svg(src: &str, elem: &Element, out: &mut String) {
+    let allowed = SVG_ELEMENTS.contains(&elem.tag.as_str()) || elem.tag.starts_with("fe");
+    if !allowed {
+        return;
+    }
+
+    let attrs = elem.attrs.iter().filter(|(k, v)| {
+        let value = v.trim_start().to_ascii_lowercase();
+        match k.as_str() {
+            "href" | "xlink:href" => {
+                value.starts_with('#')
+                    || ["png", "jpeg", "gif", "webp"]
+                        .iter()
+                        .any(|ext| value.starts_with(&format!("data:image/{ext}")))
+            }
+            _ => {
+                is_valid_name(k)
+                    && !k.starts_with("on")
+                    && !value.starts_with("javascript:")
+                    && !loads_resource(&value)
+            }
+        }
+    });
+    out.push_str(&start_tag(&elem.tag, &attrs.cloned().collect::<Vec<_>>()));
+
+    // The text between the children is kept as is, which has no markup.
+    let mut pos = elem.inner_start;
+    for child in &elem.children {
+        out.push_str(strip_markup(&src[pos..child.start]));
+        sanitize_svg(src, child, out);
+        pos = child.end;
+    }
+    out.push_str(strip_markup(&src[pos..elem.inner_end.max(pos)]));
+    out.push_str(&format!("</{}>", elem.tag));
+}
+
+/// Whether a lowercased attribute value references a resource outside of the
+/// svg, i.e. a `url()` not pointing at an element by `#id`. The css escapes,
+/// which can spell a `url(`, are also rejected.
+fn loads_resource(value: &str) -> bool {
+    value.contains('\\')
+        || value.split("url(").skip(1).any(|arg| {
+            let arg = arg.trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'');
+            !arg.starts_with('#')
+        })
+}
+
+/// Drops the comments and declarations in the text between elements.
+fn strip_markup(text: &str) -> &str {
+    if text.contains('<') {
+        ""
+    } else {
+        text
+    }
+}
+
+/// Renders a code block hydrated as a playground by the theme.
+struct PlaygroundHandler;
+
+#[derive(Deserialize)]
+struct PlaygroundArgs {
+    code: String,
+    #[serde(default)]
+    lang: Option<String>,
+    #[serde(default)]
+    editable: bool,
+}
+
+impl XCommandHandler for PlaygroundHandler {
+    fn render(&self, payload: &str) -> Option<String> {
+        let args = serde_json::from_str::<PlaygroundArgs>(payload).ok()?;
+
+        let mut attrs = vec![("class".to_owned(), "shiroa-playground".to_owned())];
+        let mut code_attrs = vec![];
+        if let Some(lang) = args.lang.filter(|lang| is_valid_name(lang)) {
+            attrs.push(("data-lang".to_owned(), lang.clone()));
+            code_attrs.push(("class".to_owned(), format!("language-{lang}")));
+        }
+        if args.editable {
+            attrs.push(("data-editable".to_owned(), String::new()));
+        }
+
+        Some(format!(
+            "{}{}{}</code></pre>",
+            start_tag("pre", &attrs),
+            start_tag("code", &code_attrs),
+            escape_str::<PcDataEscapes>(&args.code),
+        ))
+    }
+}
+
+/// Renders a disclosure widget, i.e. a `<details>` element.
+struct DetailsHandler;
+
+#[derive(Deserialize)]
+struct DetailsArgs {
+    summary: String,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    open: bool,
+}
+
+impl XCommandHandler for DetailsHandler {
+    fn render(&self, payload: &str) -> Option<String> {
+        let args = serde_json::from_str::<DetailsArgs>(payload).ok()?;
+
+        let mut attrs = vec![("class".to_owned(), "shiroa-details".to_owned())];
+        if args.open {
+            attrs.push(("open".to_owned(), String::new()));
+        }
+        Some(format!(
+            "{}<summary>{}</summary>{}</details>",
+            start_tag("details", &attrs),
+            escape_str::<PcDataEscapes>(&args.summary),
+            paragraphs(&args.body),
+        ))
+    }
+}
+
+/// Renders an element declared in build-meta, e.g. by a theme. The string
+/// fields of the payload are set as `data-*` attributes, except that
+/// `content` is the text of the element and `class` extends the declared
+/// class.
+struct DeclaredHandler(XCommandMeta);
+
+impl XCommandHandler for DeclaredHandler {
+    fn render(&self, payload: &str) -> Option<String> {
+        let fields = serde_json::from_str::<BTreeMap<String, serde_json::Value>>(payload).ok()?;
+        let meta = &self.0;
+
+        let mut class = meta.class.clone().unwrap_or_default();
+        let mut content = String::new();
+        let mut attrs = meta.attributes.clone();
+        for (k, v) in fields {
+            let value = match v {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Number(n) => n.to_string(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                _ => continue,
+            };
+            match k.as_str() {
+                "content" => content = value,
+                "class" => {
+                    class = format!("{class} {value}").trim().to_owned();
+                }
+                _ if is_valid_name(&k) => {
+                    attrs.entry(format!("data-{k}")).or_insert(value);
+                }
+                _ => {}
+            }
+        }
+        if !class.is_empty() {
+            attrs.insert("class".to_owned(), class);
+        }
+
+        Some(format!(
+            "{}{}</{}>",
+            start_tag(&meta.tag, &attrs.into_iter().collect::<Vec<_>>()),
+            paragraphs(&content),
+            meta.tag
+        ))
+    }
+}
+
+/// Renders the paragraphs of a text, which are separated by blank lines.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|par| !par.is_empty())
+        .map(|par| format!("<p>{}</p>", escape_str::<PcDataEscapes>(par)))
+        .collect()
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> XCommandRegistry {
+        XCommandRegistry::new(Arc::new(EmbedPolicy::new(None, None).unwrap()))
+    }
+
+    fn placeholder(kind: &str, payload: &str) -> String {
+        let payload = payload.replace('&', "&amp;").replace('"', "&quot;");
+        format!(r#"<div {XCOMMAND_MARKER}="{kind}" {XCOMMAND_PAYLOAD}="{payload}"></div>"#)
+    }
+
+    #[test]
+    fn placeholders_are_replaced_by_rendered_commands() {
+        let details = placeholder("details", r#"{"summary":"a & b","body":"x\n\ny"}"#);
+        let html = format!("<main><p>before</p>{details}<p>after</p></main>");
+
+        let (html, failures) = registry().expand_html(&html);
+        assert!(failures.is_empty());
+        assert_eq!(
+            html,
+            "<main><p>before</p>\
+            <details class=\"shiroa-details\"><summary>a &amp; b</summary><p>x</p><p>y</p></details>\
+            <p>after</p></main>"
+        );
+    }
+
+    #[test]
+    fn placeholders_in_raw_text_are_kept() {
+        let html = format!(
+            "<script>let s = '{}';</script>",
+            placeholder("details", "{}")
+        );
+        let (expanded, failures) = registry().expand_html(&html);
+        assert!(failures.is_empty());
+        assert_eq!(expanded, html);
+    }
+
+    #[test]
+    fn nested_placeholders_are_replaced_with_the_outer_one() {
+        let inner = placeholder("details", r#"{"summary":"inner"}"#);
+        let outer = placeholder("details", r#"{"summary":"outer"}"#);
+        let outer = outer.replace("></div>", &format!(">{inner}</div>"));
+        let html = format!("<section>{outer}{inner}</section>");
+
+        let (html, failures) = registry().expand_html(&html);
+        assert!(failures.is_empty());
+        assert_eq!(html.matches("<details").count(), 2);
+        assert!(html.contains("<summary>outer</summary>"));
+        assert!(html.contains("<summary>inner</summary>"));
+        assert!(!html.contains(XCOMMAND_MARKER));
+    }
+
+    #[test]
+    fn failed_placeholders_are_removed() {
+        let unknown = placeholder("unknown", "{}");
+        let invalid = placeholder("details", "not json");
+        let html = format!("<p>{unknown}</p>{invalid}");
+
+        let (expanded, failures) = registry().expand_html(&html);
+        assert_eq!(expanded, "<p></p>");
+        let messages = failures
+            .iter()
+            .map(|f| f.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "unknown xcommand: unknown",
+                "invalid payload of xcommand: details"
+            ]
+        );
+        assert_eq!(failures[0].offset, html.find("<div").unwrap());
+    }
+
+    #[test]
+    fn svg_is_sanitized() {
+        let svg = r#"<svg viewBox="0 0 1 1" onload="alert(1)"><script>alert(1)</script><rect width="1" fill="url(http://x)"/><rect fill="url('https://x')"/><rect fill='url("http://x")'/><rect fill="url(//x/y)"/><rect fill="url( http://x)"/><rect style="fill: u\72l(http://x)"/><rect fill="url(#g)" stroke="url( '#g' )"/><a href="javascript:x"><circle r="1"/></a></svg>"#;
+        let payload = serde_json::json!({ "svg": svg }).to_string();
+
+        // The names are lowercased as html, whose parser restores the case of
+        // the svg attributes.
+        let svg = registry().execute(&format!("svg,{payload}")).unwrap();
+        assert_eq!(
+            svg,
+            r#"<svg viewbox="0 0 1 1"><rect width="1"></rect><rect></rect><rect></rect><rect></rect><rect></rect><rect></rect><rect fill="url(#g)" stroke="url( '#g' )"></rect></svg>"#
+        );
+    }
+
+    #[test]
+    fn declared_commands_are_checked() {
+        let meta = |tag: &str| XCommandMeta {
+            tag: tag.to_owned(),
+            ..Default::default()
+        };
+
+        let mut registry = registry();
+        assert!(registry.declare("svg", &meta("div")).is_err());
+        assert!(registry.declare("script", &meta("Script")).is_err());
+        registry.declare("note", &meta("aside")).unwrap();
+
+        let note = registry.execute(r#"note,{"content":"hi","kind":"info","bad name":"x"}"#);
+        assert_eq!(
+            note.unwrap(),
+            r#"<aside data-kind="info"><p>hi</p></aside>"#
+        );
+    }
+}
@@ -2,9 +2,10 @@ pub mod interner;
 
 use std::{
     borrow::Cow,
+    collections::BTreeSet,
     fs, io,
     path::{Path, PathBuf},
-    sync::LazyLock,
+    sync::{LazyLock, Mutex},
 };
 
 use reflexo_typst::error::prelude::*;
@@ -18,6 +19,26 @@ pub fn collapse_whitespace(text: &str) -> Cow<'_, str> {
     RE.replace_all(text, " ")
 }
 
+/// Converts a text into a lowercase, URL-friendly slug, e.g. `Hello, World!`
+/// becomes `hello-world`. Non-ASCII letters are kept as they are.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let len = slug.trim_end_matches('-').len();
+    slug.truncate(len);
+    slug
+}
+
+/// Runs an async command to the end of the process. The runtime is
+/// multi-threaded, since the watch loop blocks its workers by
+/// `block_in_place`.
 pub fn async_continue<F: std::future::Future<Output = ()>>(f: F) -> ! {
     Builder::new_multi_thread()
         .enable_all()
@@ -31,6 +52,13 @@ pub fn async_continue<F: std::future::Future<Output = ()>>(f: F) -> ! {
     }
 }
 
+/// Opens an url in the default web browser.
+pub fn open_browser(url: &str) {
+    if let Err(err) = open::that_detached(url) {
+        crate::tui_warn!("failed to open {url} in the browser: {err}");
+    }
+}
+
 pub fn exit_with_error<E: std::error::Error>(err: E) -> ! {
     clap::Error::raw(
         clap::error::ErrorKind::ValueValidation,
@@ -89,6 +117,35 @@ pub fn create_dirs<P: AsRef<Path>>(path: P) -> Result<()> {
     fs::create_dir_all(path).map_err(error_once_map!("create_dirs"))
 }
 
+/// Records the files written by a build, which are used to find the stale
+/// outputs of a book.
+#[derive(Debug, Default)]
+pub struct WrittenFiles(Mutex<BTreeSet<PathBuf>>);
+
+impl WrittenFiles {
+    /// Writes a file by [`write_file`] and records it, even if its content is
+    /// unchanged.
+    pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> Result<()> {
+        let path = path.as_ref();
+        self.0.lock().unwrap().insert(path.to_owned());
+        write_file(path, contents)
+    }
+
+    /// Removes a file written since the last call, e.g. the module of a
+    /// chapter that turns out to be a draft.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        if self.0.lock().unwrap().remove(path) {
+            fs::remove_file(path).map_err(error_once_map!("remove written file"))?;
+        }
+        Ok(())
+    }
+
+    /// Takes the files recorded since the last call.
+    pub fn take(&self) -> BTreeSet<PathBuf> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
 pub fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
     let path = path.as_ref();
     if path.exists() {
@@ -121,3 +178,20 @@ pub fn copy_dir_embedded(src: &include_dir::Dir, dst: &Path) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texts_are_slugified() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Getting   Started  "), "getting-started");
+        assert_eq!(slugify("C++ & Rust"), "c-rust");
+        assert_eq!(slugify("--a--b--"), "a-b");
+        assert_eq!(slugify("Ünïcode 标题"), "ünïcode-标题");
+        assert_eq!(slugify("1.2 Intro"), "1-2-intro");
+        assert_eq!(slugify("!?"), "");
+        assert_eq!(slugify(""), "");
+    }
+}
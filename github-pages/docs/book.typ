@@ -33,6 +33,7 @@
         // - #chapter(none, "= Introduction")
         // - #chapter(none)[#text("= Introduction")]
         - #chapter("format/build-meta.typ")[Build Metadata]
+        - #chapter("format/page-meta.typ")[Page Metadata]
       - #chapter("format/theme.typ")[Theme]
       - #chapter("supports.typ")[Typst Support]
         - #chapter("supports/cross-ref.typ")[Cross Reference]
@@ -1,4 +1,4 @@
-#import "/github-pages/docs/book.typ": book-page
+#import "/github-pages/docs/book.typ": book-page, cross-link
 
 #show: book-page.with(title: "CLI Build Command")
 
@@ -23,6 +23,27 @@ root instead of the current working directory.
 shiroa build path/to/book
 ```
 
+== #cli-flag;meta-source
+
+The `--meta-source` option determines how the structure and metadata of the book are retrieved:
+- `strict`: they are read from `book.typ`. This is the default when the directory is given.
+- `outline`: they are inferred from the outline of a single main file. This is the default when a main file is given, either as the argument or by `--entry`.
+
+```bash
+# uses `book.typ` in the directory
+shiroa build path/to/book
+# splits `main.typ` into chapters by its headings
+shiroa build path/to/book/main.typ
+```
+
+== #cli-flag;entry
+
+The `--entry` option specifies the main file in outline mode, relative to the book's directory. It is equivalent to passing the main file as the argument.
+
+```bash
+shiroa build --entry main.typ path/to/book
+```
+
 == #cli-flag;root
 
 The `--root` option specifies the root directory for typst source files. It is interpreted relative to *current work directory of `shiroa` process*.
@@ -77,5 +98,57 @@ The `--mode` option allows you to specify the mode of rendering typst document.
 
 The dynamically rendering means that some elements will be rendered by a wasm renderer in the browser.
 
+== #cli-flag;keep-going
+
+By default, the build fails with a non-zero exit code and prints a summary of failed chapters when any chapter fails to compile. The `--keep-going` option renders the rest of the book instead, writes an error page for each failed chapter, and exits successfully.
+
+```bash
+shiroa build --keep-going
+```
+
+== #cli-flag;drafts
+
+Chapters marked with `draft: true` in the summary, or with `#page-meta(draft: true)` in the chapter file, are skipped by `build`, including their sub chapters. They are neither written, indexed for search nor listed in the sidebar. The `--drafts` option includes them. The `serve` command always includes them.
+
+```typ
+- #chapter("wip.typ", draft: true)[Work in progress]
+```
+
+== #cli-flag;outline-split-level
+
+When the meta source is `outline`, the main file is split into chapters by its headings. The `--outline-split-level` option specifies the deepest heading level that starts a new chapter, and the deeper headings stay in the chapter of their parent. By default, every heading starts a new chapter.
+
+The file name of each chapter is derived from its heading text, e.g. `= Getting Started` is rendered to `getting-started.html`, and chapters with the same title are disambiguated by a numeric suffix. To pin the URL of a chapter, give the heading a label, e.g. `= Getting Started <install>` is rendered to `install.html`.
+
+```bash
+shiroa build --outline-split-level 2 main.typ
+```
+
+If the headings are numbered, e.g. by `set heading(numbering: "1.1")`, the numbers are displayed in the sidebar as the heading counter shows them, including counter updates and numbering functions. Otherwise, the chapters are numbered by their position in the outline.
+
+The book metadata is also inferred from the main file: the title, authors and description come from `set document(..)`, and the language comes from `set text(lang: ..)`. If the main file contains `#book-meta(..)` or `#build-meta(..)`, the manually set fields are used first, while the summary is still inferred from the headings.
+
+With `--mode static-html`, the main file is compiled once and its html document is split by the same headings. Each page gets the title of its heading, and the links to the labels in other pages point at those pages. The content before the first heading is rendered to `pre.html`.
+
+== #cli-flag;outline-parts
+
+The `--outline-parts` option turns the top-level headings into part titles of the sidebar, and their sub-headings into chapters. The content between a top-level heading and its first sub-heading is kept as an unnumbered chapter.
+
+```bash
+shiroa build --outline-parts main.typ
+```
+
+== #cli-flag;clean
+
+Each build records the files it produces in `.shiroa-outputs.json` in the build directory. With the `--clean` option, the files produced by the last build but not by this build, e.g. the pages of renamed or removed chapters, are removed. Only the recorded files in the build directory are removed, and nothing is removed if any chapter fails to compile. See also the #cross-link("/cli/clean.typ")[clean command].
+
+```bash
+shiroa build --clean
+```
+
+= Internal assets
+
+The scripts and the renderer module used by the themes are written to the `internal` directory with fingerprinted names, e.g. `internal/shiroa.0123456789abcdef.js`, so that a CDN never serves stale assets after upgrading shiroa. The fingerprinted names are listed in `asset-manifest.json`, keyed by the original names, and the themes resolve the urls by `asset-url("shiroa.js")`.
+
 // todo: copy all rest files
 // ***Note:*** *The build command copies all files (excluding files with `.typ` extension) from the source directory into the build directory.*
@@ -1,5 +1,21 @@
-#import "/github-pages/docs/book.typ": book-page
+#import "/github-pages/docs/book.typ": book-page, cross-link
 
 #show: book-page.with(title: "CLI Clean Command")
 
-Not yet implemented.
+#let cli-flag = "--"
+
+The clean command removes the outputs of the last build:
+
+```bash
+shiroa clean
+```
+
+Each build records the files it produces in `.shiroa-outputs.json` in the build directory, and the clean command removes exactly these files, the directories that become empty, and the manifest itself. Other files in the build directory are kept. A build directory that contains the book's root directory is never cleaned.
+
+= Specify a directory
+
+The `clean` command can take a directory as an argument to use as the book's root instead of the current working directory. The build directory is determined in the same way as the #cross-link("/cli/build.typ")[build command], e.g. by `--dest-dir` or `build-meta`.
+
+```bash
+shiroa clean path/to/book
+```
@@ -25,6 +25,12 @@ shiroa init --dest-dir ../dist my-book/
 shiroa build my-book/ # memoryized dest-dir
 ```
 
+After initializing the book, `init` serves it as the #cross-link("/cli/serve.typ")[serve command] does, which accepts the `--addr` and `--open` options.
+
+```bash
+shiroa init --open my-book/
+```
+
 = Initializing a book project manually
 
 This section describes what are required by shiroa to build a book successfully.
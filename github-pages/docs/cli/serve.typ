@@ -34,17 +34,69 @@ The `serve` command will build your book once before serving the content. It is
 
 = Server options
 
-The `serve` address defaults to `localhost:25520`. Either option can be specified on the command line:
+The `serve` address defaults to `127.0.0.1:25520`, and can be specified by the `--addr` option. If the port is in use, the following ports are tried, and the actual address is printed:
 
 ```bash
-shiroa serve path/to/book --addr 8000:127.0.0.1
+shiroa serve path/to/book --addr 127.0.0.1:8000
 ```
 
+The book is served under the `--path-to-root`, as it is deployed, e.g. GitHub Pages serves a project site under `/<repository>/`. With `--path-to-root /shiroa/`, the book is served at `http://127.0.0.1:25520/shiroa/`, and the site root redirects to it.
+
+The fingerprinted assets in the `internal` directory are served with a long-lived `Cache-Control: public, max-age=31536000, immutable` header. When `csp` is enabled in #cross-link("/format/build-meta.typ")[build-meta], the content security policy of each page is sent as a `Content-Security-Policy` header, which also allows the live reload script.
+
+The moved pages declared by `redirects` in #cross-link("/format/build-meta.typ")[build-meta] are responded by `301` redirects. The unknown urls are responded by `404.html` with the `404` status, if the book has a #cross-link("/format/build-meta.typ")[not-found page].
+
 == #cli-flag;open
 
 When you use the `--open` flag, shiroa will open the rendered book in
 your default web browser after building it.
 
+```bash
+shiroa serve --open
+```
+
+== #cli-flag;https, #cli-flag;tls-cert, #cli-flag;tls-key
+
+Some Web APIs, e.g. the clipboard, are only available in a secure context. The `--https` flag serves the book over HTTPS with a self-signed certificate for `localhost`, which is generated on start and must be trusted in the browser. To use your own certificate, e.g. one created by `mkcert`, pass the PEM files by `--tls-cert` and `--tls-key`, which implies `--https`.
+
+```bash
+shiroa serve --tls-cert localhost.pem --tls-key localhost-key.pem
+```
+
+== #cli-flag;header
+
+The `--header` option adds a header to every response, and can be repeated. For example, `SharedArrayBuffer` requires the page to be cross-origin isolated:
+
+```bash
+shiroa serve \
+  --header 'Cross-Origin-Opener-Policy: same-origin' \
+  --header 'Cross-Origin-Embedder-Policy: require-corp'
+```
+
+== #cli-flag;book
+
+The books that link to each other, e.g. by `external-book` in `summary.typ`, can be served together by the `--book` option, which mounts another book directory under a path to root and can be repeated. Each book is built with the same compile options, and is watched and live reloaded on its own.
+
+```bash
+shiroa serve docs --path-to-root /guide/ --book /api/=api-docs
+```
+
+The main book is served at `http://127.0.0.1:25520/guide/`, and the book in `api-docs` is served at `http://127.0.0.1:25520/api/`.
+
+= Control endpoints
+
+The server has the endpoints to control the rebuilding, under the path to root of each book:
+
+- `POST /__shiroa/rebuild` rebuilds the whole book and reloads the pages, e.g. when the files are changed without notifying the watcher. The requests from the pages of other sites, i.e. with an `Origin` not at the `Host` of the server, are refused.
+- `GET /__shiroa/status` responds the state of the watcher in JSON, including whether a build is running, the chapters opened in the browsers (`activeFiles`), which are the only ones recompiled on changes, and the timings of the last build and its chapters (`lastBuild`).
+
+```bash
+curl -X POST http://127.0.0.1:25520/__shiroa/rebuild
+curl http://127.0.0.1:25520/__shiroa/status
+```
+
+They are only meant for local development. The server stops on `Ctrl-C`, after the running build is finished.
+
 // == Specify exclude patterns
 
 // The `serve` command will not automatically trigger a build for files listed in
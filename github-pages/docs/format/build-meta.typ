@@ -1,4 +1,4 @@
-#import "/github-pages/docs/book.typ": book-page
+#import "/github-pages/docs/book.typ": book-page, cross-link
 
 #show: book-page.with(title: "Build Metadata")
 
@@ -25,3 +25,109 @@ The directory to put the rendered book in. By default this is `book/` in the boo
 ```
 
 When you set it to `../dist`, `shiroa` will output the rendered book to `parent/to/book.typ/../../dist` or calculated `parent/dist`.
+
+= site-url #type-hint("string")
+
+The url of the deployed site, e.g. `https://example.com`. When it is set, each page gets a `<link rel="canonical">` and an `og:url` pointing to its absolute url, unless the page sets its own `canonical-url` by `#page-meta`. The `--path-to-root` option is included in the url.
+
+```typ
+#build-meta(
+  site-url: "https://myriad-dreamin.github.io",
+)
+```
+
+= social-image #type-hint("bool")
+
+Whether to render the first page of each chapter as its social preview image. The page is scaled to a 1200×630 pixels card, cropping or padding its bottom. The image is placed next to the page with the `.png` extension, e.g. `guide/install.png`, and is referenced by `og:image` and `twitter:card`. It requires `site-url`, since the OpenGraph images must be absolute urls. The chapters split from a single file in outline mode don't get an image.
+
+When an `og-card.typ` file exists in the book's root directory, it is compiled for each chapter as its social preview image instead, which also works for the chapters in outline mode. The chapter is passed as `x-og-card`, a `(title, book-title, section)`, and the themes provide an `og-card` function to render a default card:
+
+```typ
+#import "@preview/shiroa:0.4.0": x-og-card
+#import "@preview/shiroa-starlight:0.4.0": og-card
+
+#og-card(..x-og-card)
+```
+
+= not-found #type-hint("string")
+
+The template of the not-found page, relative to the book's root directory. It is compiled to `404.html` in the build directory, which static hosts like GitHub Pages and Netlify serve for the unknown urls, and so does the `serve` command. Defaults to `404.typ` if it exists. The template is compiled with `x-not-found` set to `true`, and the themes provide a `not-found` function to render a default body:
+
+```typ
+#import "@preview/shiroa-starlight:0.4.0": not-found
+#import "/templates/page.typ": project
+
+#show: project.with(title: "Page not found")
+
+#not-found()
+```
+
+Since the page is served at any url, the links and assets in it must be absolute, which is the case when they are resolved by `x-url-base`, e.g. by `cross-link` and `asset-url`.
+
+= redirects #type-hint("dictionary")
+
+The redirects of the moved pages, mapping the old paths to the new paths. A path is relative to the book's root directory, and is either a chapter file, e.g. `guide/old.typ`, or a page, e.g. `guide/old.html`. A new path can also be an absolute url, or have a fragment, e.g. `guide/new.typ#install`.
+
+```typ
+#build-meta(
+  redirects: (
+    "guide/old.typ": "guide/new.typ",
+    "install.typ": "guide/getting-started.typ#install",
+  ),
+)
+```
+
+For each old path, the build writes a stub page, which redirects to the new path by `<meta http-equiv="refresh">` and points its canonical url to the new path. The redirects are also written to the `_redirects` file in the build directory, which is read by static hosts such as Netlify and Cloudflare Pages, and the `serve` command responds them with `301` redirects. The build warns when a new path isn't a chapter, and skips the old paths that are still chapters.
+
+= embed #type-hint("dictionary")
+
+The policy of the html elements embedded by the `media` module, e.g. `media.iframe`. A violating element is dropped from the page, and a warning is reported.
+
+- `schemes`: the allowed url schemes of the `src` attributes. Defaults to `("http", "https")`.
+- `tags`: the allowed tags, mapping each tag to its policy. When it is set, it replaces the builtin policy of `iframe`, `div`, `audio` and `video`.
+  - `attributes`: the allowed attributes. The `data-*` attributes are always allowed.
+  - `hosts`: the allowed hosts of the `src` attribute, where `*.example.com` matches the subdomains. Defaults to the hosts matching the `--allowed-url-source` regex.
+  - `force`: the attributes that are always set, overriding the ones set by the author.
+
+```typ
+#build-meta(
+  embed: (
+    schemes: ("https",),
+    tags: (
+      iframe: (
+        attributes: ("src", "width", "height", "allowfullscreen"),
+        hosts: ("www.youtube.com", "*.observablehq.com"),
+        force: (
+          sandbox: "allow-scripts allow-same-origin",
+          loading: "lazy",
+          referrerpolicy: "no-referrer",
+        ),
+      ),
+    ),
+  ),
+)
+```
+
+= xcommands #type-hint("dictionary")
+
+The handlers of the embedded commands declared by the book or its theme, keyed by the command kind. Each handler has a `tag`, a `class` and fixed `attributes`. See #cross-link("/supports/embed-html.typ")[Embed Sanitized HTML Elements] for the usage.
+
+```typ
+#build-meta(xcommands: (aside: (tag: "aside", class: "starlight-aside")))
+```
+
+= csp #type-hint("bool")
+
+Whether to emit a strict content security policy for each page. The policy is computed from the rendered page:
+
+- the inline scripts and event handlers are allowed by their `sha256` hashes, instead of `'unsafe-inline'`.
+- `frame-src` and `media-src` list the hosts allowed by the `embed` policy. If the hosts of a tag are checked by `--allowed-url-source` instead, they can't be listed, so any host in the allowed `schemes` is allowed, e.g. `https:`, and `build` warns about it. Without allowed frames, `frame-src` is `'none'`.
+- `'wasm-unsafe-eval'` is only allowed in the `dyn-paged` modes, which compile the renderer module in the browser.
+
+The policy is not strict for styles: `style-src` allows `'unsafe-inline'`, since the typst html output and the themes set the styles of elements by their `style` attributes.
+
+The policy is inserted into the `<head>` of each page as a `<meta http-equiv="Content-Security-Policy">`, and is also written to the `_headers` file in the build directory, which is read by static hosts such as Netlify and Cloudflare Pages. The `serve` command sends it as a header.
+
+```typ
+#build-meta(csp: true)
+```
@@ -0,0 +1,85 @@
+#import "/github-pages/docs/book.typ": book-page
+
+#show: book-page.with(title: "Page Metadata")
+
+#let type-hint(t, required: false) = {
+  {
+    set text(weight: 400, size: 16pt)
+    if required {
+      " (required) "
+    }
+  }
+  {
+    text(fill: red, raw(t))
+  }
+}
+
+Each chapter can set its own metadata with `page-meta`. `shiroa` queries it when compiling the chapter. If a chapter sets it more than once, the last one wins.
+
+```typ
+#import "@preview/shiroa:0.4.0": page-meta
+
+#page-meta(
+  description: "How to install shiroa",
+  tags: ("guide", "installation"),
+)
+```
+
+= title #type-hint("string")
+
+Overrides the title of the chapter in the sidebar, the tag pages and the search index.
+
+= description #type-hint("string")
+
+Overrides the description of the page, which is added as `<meta name="description">` in the html `<head>` and indexed for search.
+
+= tags #type-hint("array")
+
+The tags of the page.
+
+= draft #type-hint("bool")
+
+Whether the page is a draft.
+
+= weight #type-hint("int")
+
+The weight of the page among its siblings in the sidebar. The adjacent chapters are ordered by their weights, from the lightest one, followed by the ones without weights in the order of the summary. It only takes effect if the summary is given by `book.typ`.
+
+= canonical-url #type-hint("string")
+
+The canonical URL of the page, which is added as `<link rel="canonical">` in the html `<head>`. It defaults to the absolute url of the page if `site-url` is set in `build-meta`.
+
+= image #type-hint("string")
+
+The url of the social preview image of the page, which is added as `og:image`. It overrides the image rendered by `social-image` in `build-meta`.
+
+The OpenGraph tags `og:title`, `og:description`, `og:url` and `og:image`, and a `twitter:card` are added to each page from the title, the description, the canonical url and the image.
+
+= meta #type-hint("dictionary")
+
+Custom `<meta>` tags of the page, mapping names to contents.
+
+```typ
+#page-meta(meta: (keywords: "typst, book"))
+```
+
+= Tag pages
+
+When a `tags.typ` file exists in the book's root directory, `shiroa build` compiles it into `tags/index.html` and a `tags/{slug}.html` for each tag. The tags are passed through `sys.inputs`:
+
+- `x-tags`: an array of `(name, slug, pages)`, where each page is a `(title, link)`.
+- `x-tag`: the name of the current tag, or `none` on the index page.
+
+```typ
+#import "@preview/shiroa:0.4.0": cross-link, x-tag, x-tags
+#import "/book.typ": book-page
+
+#show: book-page.with(title: if x-tag == none { "Tags" } else { "Tag: " + x-tag })
+
+#for tag in x-tags.filter(it => x-tag == none or it.name == x-tag) [
+  = #tag.name
+  #for page in tag.pages [
+    - #cross-link(page.link, page.title)
+  ]
+]
+```
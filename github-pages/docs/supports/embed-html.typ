@@ -28,8 +28,38 @@ Example:
 )
 ```
 
+The embedded elements are checked against a policy. By default, only `iframe`, `div`, `audio` and `video` are allowed, with a few attributes, and the `src` must be an `http` or `https` url whose host matches the `--allowed-url-source` regex, which defaults to `player.bilibili.com`. A violating element is dropped, and a warning is reported. The policy can be customized by `embed` in #cross-link("/format/build-meta.typ")[build-meta].
+
+The html output of each chapter is also validated against the policy, so that the policy applies in every `--mode`. Besides the elements created by the `media` module, the elements embedding external content, e.g. `iframe`, `object`, `embed`, `audio` and `video`, are checked wherever they are in a page, including the ones created by `html.elem` directly, whose violations are reported at the `html.elem` calls. The content of an embedded element, e.g. a `<source>` in a `<video>`, must also be allowed by the policy.
+
 Check the #cross-link("/supports/multimedia.typ")[Multimedia Components] to see the result of the above code.
 
 Explaination:
 - `outer-width` and `outer-height` gives a the size to render at the position. You can either use the `shiroa.page-width` or `std.layout` to determine a proper size.
 - The `media` components currently doesn't get render in PDF output, so you have to provide the alternative content when `is-pdf-target` is ```typc true```.
+
+= Embedded commands
+
+The `media` components are built on embedded commands, each of which carries a kind and a payload, and is rendered as raw html by the handler of its kind in shiroa. Besides `html`, which renders the components above, there are builtin handlers:
+
+- `media.svg(src)`: Embed inline svg. The scripts, the `foreignObject` elements, the event handlers and the links to external resources are removed.
+- `media.playground(code, lang: auto, editable: false)`: Embed a code block as a `<pre class="shiroa-playground">`, which is hydrated as a playground by the theme.
+- `media.details(summary, body, open: false)`: Embed a disclosure widget, i.e. a `<details>` element.
+
+```typ
+#media.details(outer-width: 640pt, outer-height: 120pt)[Show the answer][The answer is 42.]
+```
+
+A book or a theme can declare more handlers by `xcommands` in #cross-link("/format/build-meta.typ")[build-meta], and embed them by `media.xcommand-of(kind, payload)`. A declared handler renders an element with the `tag` (defaulting to `div`), the `class` and the fixed `attributes`. The `content` in the payload is the text of the element, the `class` in the payload extends the declared class, and the rest of the payload is set as `data-*` attributes. The builtin kinds can't be redeclared, and the tags that load scripts or external content, e.g. `script` and `iframe`, can't be declared.
+
+```typ
+#build-meta(
+  xcommands: (
+    aside: (tag: "aside", class: "starlight-aside"),
+  ),
+)
+
+#media.xcommand-of("aside", (class: "starlight-aside--tip", content: "Try it out!"), outer-height: 60pt)
+```
+
+The starlight theme provides its declarations as `xcommands`, e.g. ```typc build-meta(xcommands: starlight.xcommands)```.
@@ -12,6 +12,7 @@
 //!   - `book`
 //!   - `book-meta`
 //!   - `build-meta`
+//!   - `page-meta`
 //!   - `chapter`
 //!   - `prefix-chapter`
 //!   - `suffix-chapter`
@@ -35,7 +36,7 @@
 #import "supports-text.typ" as text-support: plain-text
 #import "supports-html.typ" as html-support
 #import "media.typ"
-#import "utils.typ": get-book-meta, get-build-meta
+#import "utils.typ": get-book-meta, get-build-meta, get-page-meta
 
 // Part IV: Templates, todo: move me to a new package
-#import "templates.typ" as templates: paged-load-trampoline, prepare-description
+#import "templates.typ" as templates: page-head-meta, paged-load-trampoline, prepare-description
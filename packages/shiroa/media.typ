@@ -1,8 +1,9 @@
 
 #import "xcommand.typ": xcommand, xcommand-html
 #import "meta-and-state.typ": shiroa-sys-target
+#import "supports-text.typ": plain-text
 
-#let xhtml(..args, tag: none, attributes: (:)) = context if shiroa-sys-target() == "paged" {
+#let _xhtml(..args, tag: none, attributes: (:)) = context if shiroa-sys-target() == "paged" {
   xcommand(
     ..args,
     {
@@ -22,7 +23,49 @@
   )
 }
 
+#let xhtml(..args, tag: none, attributes: (:)) = {
+  // Queried by shiroa to check the element against the embed policy. The
+  // arguments carry the span of the call, where the violations are reported.
+  [#metadata((tag: tag, attributes: attributes, call: args)) <shiroa-embed>]
+  _xhtml(..args, tag: tag, attributes: attributes)
+}
+
 #let iframe = xhtml.with(tag: "iframe")
 #let video = xhtml.with(tag: "video")
 #let audio = xhtml.with(tag: "audio")
 #let div = xhtml.with(tag: "div")
+
+/// Embeds a command rendered by the handler of `kind` in shiroa, e.g. `svg`, or a handler declared in `build-meta(xcommands: ..)`.
+///
+/// - kind (str): The kind of the command.
+/// - payload (dictionary): The payload passed to the handler.
+#let xcommand-of(kind, payload, ..args) = context if shiroa-sys-target() == "paged" {
+  xcommand(..args, kind + "," + json.encode(payload))
+} else {
+  // Replaced by the rendered command when shiroa writes the page.
+  html.elem("div", attrs: (data-shiroa-xcommand: kind, data-payload: json.encode(payload)))
+}
+
+#let _text(it) = if type(it) == str { it } else if it.func() == raw { it.text } else { plain-text(it) }
+
+/// Embeds inline svg, which is sanitized by removing the scripts and the external resources.
+#let svg(src, ..args) = xcommand-of("svg", (svg: _text(src)), ..args)
+
+/// Embeds a code block hydrated as a playground by the theme.
+#let playground(code, lang: auto, editable: false, ..args) = {
+  let lang = if lang != auto {
+    lang
+  } else if type(code) == content and code.func() == raw {
+    code.lang
+  } else {
+    none
+  }
+  xcommand-of("playground", (code: _text(code), lang: lang, editable: editable), ..args)
+}
+
+/// Embeds a disclosure widget, i.e. a `<details>` element.
+#let details(summary, body, open: false, ..args) = xcommand-of(
+  "details",
+  (summary: _text(summary), body: _text(body), open: open),
+  ..args,
+)
@@ -1,5 +1,7 @@
 
-#import "sys.typ": page-width, x-target, x-current, x-target, x-url-base
+#import "sys.typ": (
+  page-width, x-asset-manifest, x-current, x-include-drafts, x-not-found, x-og-card, x-page-drafts, x-page-summary, x-site-url, x-social-image, x-tag, x-tags, x-target, x-url-base,
+)
 
 /// The default page width is A4 paper's width (21cm).
 ///
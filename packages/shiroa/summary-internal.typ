@@ -23,6 +23,7 @@
       kind: "part",
       level: elem.depth,
       title: _store-content(elem.body),
+      raw-title: elem.body,
     )
   }
 
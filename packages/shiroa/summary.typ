@@ -1,6 +1,7 @@
 
 #import "utils.typ": _store-content
 #import "meta-and-state.typ": book-meta-state
+#import "sys.typ": x-page-summary
 #import "supports-link.typ": cross-link-path-label, link2page
 
 /// Show template in #link("https://myriad-dreamin.github.io/shiroa/format/book.html")[book.typ]
@@ -72,31 +73,137 @@
 /// Build metadata in #link("https://myriad-dreamin.github.io/shiroa/format/book.html")[book.typ]
 ///
 /// - dest-dir (str): The directory to put the rendered book in. By default this is `book/` in the book's root directory. This can overridden with the `--dest-dir` CLI option.
+/// - site-url (str): The url of the deployed site, e.g. `https://example.com`. It is used to generate the canonical and OpenGraph urls of each page.
+/// - social-image (bool): Whether to render the first page of each chapter as its social preview image.
+/// - not-found (str): The template of the not-found page, i.e. `404.html`, relative to the book's root directory. Defaults to `404.typ` if it exists.
+/// - redirects (dictionary): The redirects of the moved pages, mapping the old paths to the new paths, e.g. `"guide/old.typ": "guide/new.typ"`.
+/// - embed (dictionary): The policy of the html elements embedded by the `media` module, with the `schemes` and `tags` keys.
+/// - xcommands (dictionary): The handlers of the embedded commands declared by the book or its theme, keyed by the command kind.
+/// - csp (bool): Whether to emit a strict content security policy for each page, in a `<meta http-equiv>` and in the `_headers` file.
 #let build-meta(
   dest-dir: "",
+  site-url: "",
+  social-image: false,
+  not-found: "",
+  redirects: (:),
+  embed: none,
+  csp: false,
+  xcommands: (:),
 ) = [
   #let meta = (
     "dest-dir": dest-dir,
+    "site-url": site-url,
+    "social-image": social-image,
+    "not-found": not-found,
+    "redirects": redirects,
+    "embed": embed,
+    "csp": csp,
+    "xcommands": xcommands,
   )
 
   #metadata(meta) <shiroa-build-meta>
 ]
 
+/// Page metadata in a chapter file. shiroa queries it when compiling the chapter.
+/// The last one wins if a chapter sets it more than once.
+///
+/// - title (str): Overrides the title of the chapter in the sidebar and the search index.
+/// - description (str): Overrides the description of the page.
+/// - tags (array): The tags of the page.
+/// - draft (bool): Whether the page is a draft.
+/// - weight (int): The weight of the page among its siblings in the sidebar.
+/// - canonical-url (str): The canonical URL of the page.
+/// - image (str): The url of the social preview image of the page.
+/// - meta (dictionary): Custom `<meta>` tags of the page, mapping names to contents.
+///
+/// Example:
+/// ```typ
+/// #page-meta(
+///   description: "How to install shiroa",
+///   tags: ("guide", "installation"),
+/// )
+/// ```
+#let page-meta(
+  title: none,
+  description: none,
+  tags: (),
+  draft: false,
+  weight: none,
+  canonical-url: none,
+  image: none,
+  meta: (:),
+) = [
+  #assert(type(tags) == array, message: "tags must be an array of strings")
+  #assert(type(meta) == dictionary, message: "meta must be a dictionary of strings")
+  #assert(weight == none or type(weight) == int, message: "weight must be an integer")
+
+  #let meta = (
+    title: title,
+    description: description,
+    tags: tags,
+    draft: draft,
+    weight: weight,
+    "canonical-url": canonical-url,
+    image: image,
+    meta: meta,
+  )
+
+  #metadata(meta) <shiroa-page-meta>
+]
+
+/// Applies the titles and weights set by `page-meta` to the summary items, e.g.
+/// in the sidebar of a theme. The titles replace the `raw-title` of the
+/// chapters, and the adjacent chapters are ordered by their weights, followed
+/// by the ones without weights in the summary order.
+#let page-summary-items(items) = {
+  let page-of(item) = if item.kind == "chapter" and item.link != none {
+    x-page-summary.at(item.link, default: none)
+  }
+  let weight(item) = {
+    let page = page-of(item)
+    if page != none and page.weight != none { page.weight } else { calc.inf }
+  }
+
+  let runs = ()
+  for item in items {
+    if item.kind == "chapter" {
+      let page = page-of(item)
+      if page != none and page.title != none {
+        item.insert("raw-title", page.title)
+      }
+      if "sub" in item {
+        item.insert("sub", page-summary-items(item.sub))
+      }
+    }
+
+    if runs.len() > 0 and (runs.last().last().kind == "chapter") == (item.kind == "chapter") {
+      runs.last().push(item)
+    } else {
+      runs.push((item,))
+    }
+  }
+  runs.map(run => run.sorted(key: weight)).join(default: ())
+}
+
 /// Represents a chapter in the book
 /// link: path relative (from summary.typ) to the chapter
 /// title: title of the chapter
 /// section: manually specify the section number of the chapter
+/// draft: whether the chapter is a draft, which is only rendered by `shiroa serve` or `shiroa build --drafts`
 ///
 /// Example:
 /// ```typ
 /// #chapter("chapter1.typ")["Chapter 1"]
 /// #chapter("chapter2.typ", section: "1.2")["Chapter 1.2"]
+/// #chapter("chapter3.typ", draft: true)["Chapter 3"]
 /// ```
-#let chapter(link, title, section: auto) = metadata((
+#let chapter(link, title, section: auto, draft: false) = metadata((
   kind: "chapter",
   link: link,
   section: section,
+  draft: draft,
   title: _store-content(title),
+  raw-title: title,
 ))
 
 /// Represents a prefix/suffix chapter in the book
@@ -190,8 +297,7 @@
       x.sub.map(it => visit-summary(it, visit)).sum()
     }
   } else if x.at("kind") == "part" {
-    // todo: more than plain text
-    visit.at("part")(x.at("title").at("content"))
+    visit.at("part")(x.at("raw-title", default: x.at("title").at("content")))
   } else {
     // repr(x)
   }
@@ -1,6 +1,6 @@
 
 #import "html-bindings.typ": *
-#import "sys.typ": x-url-base
+#import "sys.typ": x-asset-manifest, x-url-base
 
 #import "@preview/based:0.2.0": base64
 #let data-url(mime, src) = {
@@ -35,6 +35,10 @@
 
 #let meta = meta.with[]
 
+/// Gets the url of an internal asset, which is resolved to its fingerprinted
+/// name by the asset manifest.
+#let asset-url(name) = x-url-base + "internal/" + x-asset-manifest.at(name, default: name)
+
 #let shiroa-asset-file(name, lang: "js", inline: true, is-debug: false, ..rest) = {
   if is-debug {
     let asset = raw(lang: lang, read("/assets/artifacts/" + name, encoding: none))
@@ -45,9 +49,9 @@
     }
   } else {
     if lang == "js" {
-      script(src: x-url-base + "internal/" + name, ..rest)[]
+      script(src: asset-url(name), ..rest)[]
     } else if lang == "css" {
-      h.link(rel: "stylesheet", href: x-url-base + "internal/" + name, ..rest)[]
+      h.link(rel: "stylesheet", href: asset-url(name), ..rest)[]
     } else {
       panic("Unsupported asset language: " + lang)
     }
@@ -80,7 +84,7 @@
             "application/wasm",
             read("/assets/artifacts/typst_ts_renderer_bg.wasm", encoding: none),
           )
-        } else { x-url-base + "internal/typst_ts_renderer_bg.wasm" },
+        } else { asset-url("typst_ts_renderer_bg.wasm") },
       ),
       ```js
       window.typstRerender = () => { };
@@ -29,6 +29,67 @@
 /// passing the current file path.
 #let x-current = sys.inputs.at("x-current", default: none)
 
+/// The url of the deployed site without the trailing slash, or `none` if the
+/// `site-url` is not set in `build-meta`.
+#let x-site-url = {
+  let site-url = sys.inputs.at("x-site-url", default: none)
+  if site-url != none and site-url.ends-with("/") {
+    site-url = site-url.slice(0, -1)
+  }
+  site-url
+}
+
+/// Whether a social preview image is rendered for the current page, which is
+/// placed next to the page with the `.png` extension.
+#let x-social-image = sys.inputs.at("x-social-image", default: false)
+
+/// Whether the draft chapters are rendered.
+/// `shiroa build` sets it to `false` unless `--drafts` is passed.
+#let x-include-drafts = sys.inputs.at("x-include-drafts", default: true)
+
+/// The links of the chapters marked as drafts by `page-meta`, e.g.
+/// `guide/wip.typ`, which are hidden from the summary like the draft chapters.
+#let x-page-drafts = sys.inputs.at("x-page-drafts", default: ())
+
+/// The titles and weights set by `page-meta` of the chapters, keyed by the
+/// chapter links, e.g. `guide/wip.typ`. Each is a `(title: str, weight: int)`,
+/// where either can be `none`. They are applied by `page-summary-items`.
+#let x-page-summary = {
+  let pages = sys.inputs.at("x-page-summary", default: none)
+  if pages != none { json(bytes(pages)) } else { (:) }
+}
+
+/// Experimental.
+/// The tags of the book, passed when compiling the `tags.typ` template. It is an
+/// array of `(name: str, slug: str, pages: array)`, where each page is a
+/// `(title: str, link: str)`.
+#let x-tags = {
+  let tags = sys.inputs.at("x-tags", default: none)
+  if tags != none { json(bytes(tags)) }
+}
+
+/// Experimental.
+/// The tag of the current tag page, or `none` on the tag index page.
+#let x-tag = sys.inputs.at("x-tag", default: none)
+
+/// Experimental.
+/// The page of the social preview card, passed when compiling the `og-card.typ`
+/// template. It is a `(title: str, book-title: str, section: str)`, where the
+/// `section` can be `none`.
+#let x-og-card = {
+  let card = sys.inputs.at("x-og-card", default: none)
+  if card != none { json(bytes(card)) } else { (:) }
+}
+
+/// Whether the current page is the not-found page, i.e. `404.html`, which is
+/// served at any unknown url.
+#let x-not-found = sys.inputs.at("x-not-found", default: none) == "true"
+
+/// The fingerprinted file names of the internal assets, keyed by their
+/// original names, e.g. `shiroa.js`. `shiroa build` writes the assets under
+/// the fingerprinted names, which are also listed in `asset-manifest.json`.
+#let x-asset-manifest = sys.inputs.at("x-asset-manifest", default: (:))
+
 /// It is in default A4 paper size (21cm)
 /// example:
 /// ```typc
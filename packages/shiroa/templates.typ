@@ -314,6 +314,72 @@
   }
 }
 
+/// Gets the absolute url of a page in the site, or `none` if the `site-url` is
+/// not set in `build-meta`.
+///
+/// - path (str): The path to the page source, e.g. `/guide/install.typ`.
+/// - extension (str): The extension of the output file.
+#let site-page-url(path, extension: ".html") = {
+  import "sys.typ": x-site-url, x-url-base
+  if x-site-url == none or path == none {
+    return none
+  }
+
+  let path = path.replace(regex("\.typ$"), extension)
+  if path.starts-with("/") {
+    path = path.slice(1)
+  }
+  x-site-url + x-url-base + path
+}
+
+/// Renders the head tags of the current page, preferring the ones set by `page-meta`.
+///
+/// - title (str): The title of the page, used by the OpenGraph tags.
+/// - description (str): The fallback description of the page.
+#let page-head-meta(title: none, description: none) = {
+  import "utils.typ": get-page-meta
+  import "sys.typ": x-current, x-social-image
+  get-page-meta(mapper: it => {
+    let it = if it == none { (:) } else { it }
+
+    // <meta description>
+    let page-description = it.at("description", default: none)
+    if page-description == none { page-description = description }
+    if page-description != none {
+      html.elem("meta", attrs: (name: "description", content: page-description))
+    }
+    // <link canonical>
+    let canonical-url = it.at("canonical-url", default: none)
+    if canonical-url == none { canonical-url = site-page-url(x-current) }
+    if canonical-url != none {
+      html.elem("link", attrs: (rel: "canonical", href: canonical-url))
+    }
+    // OpenGraph and Twitter cards
+    let page-title = it.at("title", default: none)
+    if page-title == none { page-title = title }
+    let image = it.at("image", default: none)
+    if image == none and x-social-image { image = site-page-url(x-current, extension: ".png") }
+    let og = (
+      "og:type": "website",
+      "og:url": canonical-url,
+      "og:title": page-title,
+      "og:description": page-description,
+      "og:image": image,
+    )
+    for (property, content) in og.pairs() {
+      if content != none and content != "" {
+        html.elem("meta", attrs: (property: property, content: content))
+      }
+    }
+    let card = if image != none { "summary_large_image" } else { "summary" }
+    html.elem("meta", attrs: (name: "twitter:card", content: card))
+    // custom <meta>
+    for (name, content) in it.at("meta", default: (:)).pairs() {
+      html.elem("meta", attrs: (name: name, content: content))
+    }
+  })
+}
+
 #let paged-load-trampoline() = {
   import "sys.typ": x-current, x-url-base
   let replace-raw(it, vars: (:)) = {
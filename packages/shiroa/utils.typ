@@ -24,3 +24,10 @@
 
 /// helper function to get (and print/use) the final build metadata
 #let get-build-meta = _labeled-meta.with(<shiroa-build-meta>)
+
+/// helper function to get (and print/use) the page metadata of the current page.
+/// The last `page-meta` wins if the page sets it more than once.
+#let get-page-meta(mapper: it => it) = context {
+  let res = query(<shiroa-page-meta>).at(-1, default: none)
+  mapper(if res != none { res.value })
+}
@@ -74,6 +74,8 @@
   html.elem(
     "div",
     attrs: (
+      // Marks the element to check against the embed policy.
+      data-shiroa-embed: tag,
       style: {
         "width: "
         str(outer-width.pt())
@@ -1,5 +1,7 @@
 
 #import "mod.typ": set-slot
+#import "og-card.typ": og-card
+#import "not-found.typ": not-found
 
 #let social-links(
   github: none,
@@ -60,9 +62,9 @@
   social-links: social-links,
   right-group: none,
 ) = {
-  import "@preview/shiroa:0.4.0": get-book-meta, is-html-target, paged-load-trampoline, plain-text, prepare-description, x-current, x-target, x-url-base
+  import "@preview/shiroa:0.4.0": get-book-meta, is-html-target, page-head-meta, paged-load-trampoline, plain-text, prepare-description, x-current, x-target, x-url-base
   import "mod.typ": inline-assets, replace-raw
-  import "html.typ": a, div, meta
+  import "html.typ": a, div
   import "icons.typ": builtin-icon
 
   if not is-html-target() {
@@ -86,8 +88,8 @@
       let resolved-site-title = book-site-title(it)
       html.elem("title", plain-text(meta-title(title, resolved-site-title)).trim())
     })
-    // <meta description>
-    if description != none { meta(name: "description", content: description) }
+    // <meta description>, <link canonical>, OpenGraph and custom <meta> tags
+    page-head-meta(title: title-text(title), description: description)
   })
 
   show: set-slot("main-title", html.elem("h1", attrs: (class: "menu-title"), title))
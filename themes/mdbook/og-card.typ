@@ -0,0 +1,32 @@
+/// Renders the social preview card of a page. It is used by the `og-card.typ`
+/// template in the book's root directory:
+///
+/// ```typ
+/// #import "@preview/shiroa:0.4.0": x-og-card
+/// #import "@preview/shiroa-mdbook:0.4.0": og-card
+///
+/// #og-card(..x-og-card)
+/// ```
+///
+/// - title (str): The title of the page.
+/// - book-title (str): The title of the book.
+/// - section (str): The section number of the page, e.g. `1.2`.
+/// - accent (color): The color of the accent bar and the section number.
+#let og-card(title: none, book-title: none, section: none, accent: rgb("#4183c4")) = {
+  set page(width: 600pt, height: 315pt, margin: 40pt, fill: rgb("#ffffff"))
+  set text(fill: rgb("#333333"), size: 16pt)
+
+  place(top + left, dx: -40pt, dy: -40pt, rect(width: 8pt, height: 315pt, fill: accent))
+
+  if book-title != none {
+    text(fill: rgb("#747474"), book-title)
+  }
+  v(1fr)
+  if section != none {
+    text(fill: accent, size: 20pt, weight: "bold", section)
+    v(4pt, weak: true)
+  }
+  if title != none {
+    block(text(size: 36pt, weight: "bold", title))
+  }
+}
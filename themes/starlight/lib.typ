@@ -1,8 +1,15 @@
 
 #import "mod.typ": set-slot
+#import "og-card.typ": og-card
+#import "not-found.typ": not-found
 #import "page-header.typ": right-group-item
 #import "icons.typ": builtin-icon
 
+/// The embedded commands rendered by the theme, declared by `build-meta(xcommands: xcommands)`. The `aside` command renders a callout, whose `class` is one of `starlight-aside--note`, `starlight-aside--tip`, `starlight-aside--caution` and `starlight-aside--danger`.
+#let xcommands = (
+  aside: (tag: "aside", class: "starlight-aside"),
+)
+
 #let social-links(
   github: none,
   discord: none,
@@ -66,8 +73,8 @@
   },
   right-group: none,
 ) = {
-  import "@preview/shiroa:0.4.0": get-book-meta, is-html-target, paged-load-trampoline, plain-text, prepare-description, x-current, x-target
-  import "html.typ": inline-assets, meta, span
+  import "@preview/shiroa:0.4.0": get-book-meta, is-html-target, page-head-meta, paged-load-trampoline, plain-text, prepare-description, x-current, x-target
+  import "html.typ": inline-assets, span
   import "mod.typ": replace-raw
 
   if not is-html-target() {
@@ -98,8 +105,8 @@
       let resolved-site-title = book-site-title(it)
       html.elem("title", plain-text(meta-title(title, resolved-site-title)).trim())
     })
-    // <meta description>
-    if description != none { meta(name: "description", content: description) }
+    // <meta description>, <link canonical>, OpenGraph and custom <meta> tags
+    page-head-meta(title: title-text(title), description: description)
   })
 
   show: set-slot("main-title", html.elem("h1", title))
@@ -0,0 +1,25 @@
+/// Renders the body of the not-found page. It is used by the `404.typ`
+/// template in the book's root directory:
+///
+/// ```typ
+/// #import "@preview/shiroa-starlight:0.4.0": not-found
+/// #import "/template.typ": project
+///
+/// #show: project
+///
+/// #not-found()
+/// ```
+///
+/// - home (str): The url of the home page. Defaults to the root of the book.
+#let not-found(home: auto) = {
+  import "@preview/shiroa:0.4.0": x-url-base
+  let home = if home == auto { x-url-base } else { home }
+
+  [
+    = Page not found
+
+    The page you are looking for doesn't exist or has been moved.
+
+    #link(home)[Go back to the home page.]
+  ]
+}
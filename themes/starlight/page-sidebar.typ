@@ -1,25 +1,25 @@
 
 #import "mod.typ": *
 #import "icons.typ": builtin-icon
-#import "@preview/shiroa:0.4.0": cross-link-path-label, get-book-meta, x-url-base
+#import "@preview/shiroa:0.4.0": cross-link-path-label, get-book-meta, page-summary-items, x-include-drafts, x-page-drafts, x-url-base
 
 #let render-sidebar(summary-items, visit) = {
   let part = none
   let items = ()
   for summary-item in summary-items {
     if summary-item.kind == "chapter" {
+      // skip draft chapters, which are not built
+      let draft = summary-item.at("draft", default: false) or summary-item.link in x-page-drafts
+      if draft and not x-include-drafts {
+        continue
+      }
+
       let v = none
 
       let link = summary-item.link
       if link != none {
         items.push(
-          visit.at("inc")("/" + link, {
-            if "raw-title" in summary-item {
-              summary-item.raw-title
-            } else {
-              summary-item.title.content
-            }
-          }),
+          visit.at("inc")("/" + link, summary-item.at("raw-title", default: summary-item.title.content)),
         )
       }
 
@@ -27,13 +27,11 @@
         items.push(visit.at("sub")(render-sidebar(summary-item.sub, visit)))
       }
     } else if summary-item.kind == "part" {
-      // todo: more than plain text
-
       if part != none or items.len() > 0 {
         visit.at("part")(part, items)
       }
 
-      part = summary-item.at("title").at("content")
+      part = summary-item.at("raw-title", default: summary-item.at("title").at("content"))
       items = ()
     } else if summary-item.kind == "partbreak" {
       if part != none or items.len() > 0 {
@@ -86,7 +84,7 @@ this.parentElement.classList.toggle("open");
 #context {
   let book-meta = query(<shiroa-book-meta>).at(0, default: none)
   if book-meta != none {
-    let sm = book-meta.value.summary
+    let sm = page-summary-items(book-meta.value.summary)
 
     let styles = (
       inc: (link, it) => cross-link2(current, link, it),
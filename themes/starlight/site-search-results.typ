@@ -27,6 +27,6 @@
   })
 })
 
-#script(src: { x-url-base + "internal/elasticlunr.min.js" })[]
-#script(src: { x-url-base + "internal/mark.min.js" })[]
-#script(src: { x-url-base + "internal/searcher.js" })[]
+#script(src: asset-url("elasticlunr.min.js"))[]
+#script(src: asset-url("mark.min.js"))[]
+#script(src: asset-url("searcher.js"))[]